@@ -0,0 +1,214 @@
+//! Conversion between raw commitments and their CID representation.
+//!
+//! Outside of the proofs, data (`comm_d`), piece (`comm_p`) and replica (`comm_r`)
+//! commitments are addressed as CIDv1 values, using the `fil-commitment-unsealed` and
+//! `fil-commitment-sealed` multicodecs together with the multihash code of the hash function
+//! which produced the commitment.
+
+use anyhow::{ensure, Result};
+use cid::multihash::Multihash;
+use cid::{Cid, Version};
+
+use crate::{Commitment, PieceInfo, UnpaddedBytesAmount};
+
+/// Multicodec for unsealed data and piece commitments.
+pub const FIL_COMMITMENT_UNSEALED: u64 = 0xf101;
+/// Multicodec for sealed replica commitments.
+pub const FIL_COMMITMENT_SEALED: u64 = 0xf102;
+
+/// Multihash code of SHA2-256 with the two most significant bits of the digest zeroed,
+/// used for `comm_d` and `comm_p`.
+pub const SHA2_256_TRUNC254_PADDED: u64 = 0x1012;
+/// Multihash code of the Poseidon hash over BLS12-381 (arity 11, first variant), used for
+/// `comm_r`.
+pub const POSEIDON_BLS12_381_A1_FC1: u64 = 0xb401;
+
+/// Converts a data commitment (`comm_d` or `comm_p`) into its CID.
+///
+/// # Arguments
+/// * `comm_d` - Data or piece commitment.
+///
+/// Returns the commitment as a `fil-commitment-unsealed` CID.
+pub fn data_commitment_to_cid(comm_d: &Commitment) -> Result<Cid> {
+    commitment_to_cid(FIL_COMMITMENT_UNSEALED, SHA2_256_TRUNC254_PADDED, comm_d)
+}
+
+/// Converts a replica commitment (`comm_r`) into its CID.
+///
+/// # Arguments
+/// * `comm_r` - Replica commitment.
+///
+/// Returns the commitment as a `fil-commitment-sealed` CID.
+pub fn replica_commitment_to_cid(comm_r: &Commitment) -> Result<Cid> {
+    commitment_to_cid(FIL_COMMITMENT_SEALED, POSEIDON_BLS12_381_A1_FC1, comm_r)
+}
+
+/// Converts the commitment of a [`PieceInfo`] into its CID. The piece size is not part of
+/// the CID and must be tracked separately by the caller.
+///
+/// # Arguments
+/// * `piece_info` - Piece whose commitment is converted.
+///
+/// Returns the piece commitment as a `fil-commitment-unsealed` CID.
+pub fn piece_info_to_cid(piece_info: &PieceInfo) -> Result<Cid> {
+    data_commitment_to_cid(&piece_info.commitment)
+}
+
+/// Extracts a data commitment (`comm_d` or `comm_p`) from a CID.
+///
+/// # Arguments
+/// * `cid` - A `fil-commitment-unsealed` CID.
+///
+/// Returns the raw data commitment.
+pub fn cid_to_data_commitment(cid: &Cid) -> Result<Commitment> {
+    cid_to_commitment(FIL_COMMITMENT_UNSEALED, SHA2_256_TRUNC254_PADDED, cid)
+}
+
+/// Extracts a replica commitment (`comm_r`) from a CID.
+///
+/// # Arguments
+/// * `cid` - A `fil-commitment-sealed` CID.
+///
+/// Returns the raw replica commitment.
+pub fn cid_to_replica_commitment(cid: &Cid) -> Result<Commitment> {
+    cid_to_commitment(FIL_COMMITMENT_SEALED, POSEIDON_BLS12_381_A1_FC1, cid)
+}
+
+/// Builds a [`PieceInfo`] from a piece CID and the unpadded size of the piece.
+///
+/// # Arguments
+/// * `cid` - A `fil-commitment-unsealed` CID.
+/// * `size` - The number of unpadded bytes in the piece.
+///
+/// Returns the piece info for the given CID.
+pub fn cid_to_piece_info(cid: &Cid, size: UnpaddedBytesAmount) -> Result<PieceInfo> {
+    let commitment = cid_to_data_commitment(cid)?;
+
+    PieceInfo::new(commitment, size)
+}
+
+fn commitment_to_cid(codec: u64, hash_code: u64, commitment: &Commitment) -> Result<Cid> {
+    validate_commitment(commitment)?;
+
+    let hash = Multihash::wrap(hash_code, commitment)?;
+
+    Ok(Cid::new_v1(codec, hash))
+}
+
+fn cid_to_commitment(codec: u64, hash_code: u64, cid: &Cid) -> Result<Commitment> {
+    ensure!(
+        cid.version() == Version::V1,
+        "invalid cid version {:?}, expected V1",
+        cid.version()
+    );
+    ensure!(
+        cid.codec() == codec,
+        "invalid cid codec {:#x}, expected {:#x}",
+        cid.codec(),
+        codec
+    );
+
+    let hash = cid.hash();
+    ensure!(
+        hash.code() == hash_code,
+        "invalid multihash code {:#x}, expected {:#x}",
+        hash.code(),
+        hash_code
+    );
+    ensure!(
+        hash.digest().len() == 32,
+        "invalid multihash digest length {}, expected 32",
+        hash.digest().len()
+    );
+
+    let mut commitment = [0; 32];
+    commitment.copy_from_slice(hash.digest());
+    validate_commitment(&commitment)?;
+
+    Ok(commitment)
+}
+
+fn validate_commitment(commitment: &Commitment) -> Result<()> {
+    ensure!(
+        fr32::bytes_into_fr(commitment).is_ok(),
+        "commitment is not a valid field element"
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A valid field element: the two most significant bits are zero.
+    const COMMITMENT: Commitment = [
+        0xfc, 0x7e, 0x92, 0x82, 0x96, 0xe5, 0x16, 0xfa, 0xad, 0xe9, 0x86, 0xb2, 0x8f, 0x92, 0xd4,
+        0x4a, 0x4f, 0x24, 0xb9, 0x35, 0x48, 0x52, 0x23, 0x37, 0x6a, 0x79, 0x90, 0x27, 0xbc, 0x18,
+        0xf8, 0x33,
+    ];
+
+    #[test]
+    fn test_data_commitment_roundtrip() {
+        let cid = data_commitment_to_cid(&COMMITMENT).expect("failed to convert comm_d");
+        assert_eq!(cid.codec(), FIL_COMMITMENT_UNSEALED);
+        assert_eq!(cid.hash().code(), SHA2_256_TRUNC254_PADDED);
+
+        let comm_d = cid_to_data_commitment(&cid).expect("failed to convert cid");
+        assert_eq!(comm_d, COMMITMENT);
+    }
+
+    #[test]
+    fn test_replica_commitment_roundtrip() {
+        let cid = replica_commitment_to_cid(&COMMITMENT).expect("failed to convert comm_r");
+        assert_eq!(cid.codec(), FIL_COMMITMENT_SEALED);
+        assert_eq!(cid.hash().code(), POSEIDON_BLS12_381_A1_FC1);
+
+        let comm_r = cid_to_replica_commitment(&cid).expect("failed to convert cid");
+        assert_eq!(comm_r, COMMITMENT);
+    }
+
+    #[test]
+    fn test_piece_info_roundtrip() {
+        let size = UnpaddedBytesAmount(2032);
+        let piece_info = PieceInfo::new(COMMITMENT, size).expect("failed to create piece info");
+
+        let cid = piece_info_to_cid(&piece_info).expect("failed to convert piece info");
+        let decoded = cid_to_piece_info(&cid, size).expect("failed to convert cid");
+        assert_eq!(decoded.commitment, COMMITMENT);
+        assert_eq!(decoded.size, size);
+    }
+
+    #[test]
+    fn test_reject_mismatched_cids() {
+        let comm_d_cid = data_commitment_to_cid(&COMMITMENT).expect("failed to convert comm_d");
+        let comm_r_cid = replica_commitment_to_cid(&COMMITMENT).expect("failed to convert comm_r");
+
+        assert!(cid_to_replica_commitment(&comm_d_cid).is_err());
+        assert!(cid_to_data_commitment(&comm_r_cid).is_err());
+
+        // Right codec, wrong hash function.
+        let hash = Multihash::wrap(POSEIDON_BLS12_381_A1_FC1, &COMMITMENT).expect("wrap failed");
+        let cid = Cid::new_v1(FIL_COMMITMENT_UNSEALED, hash);
+        assert!(cid_to_data_commitment(&cid).is_err());
+
+        // Right codec and hash function, wrong digest length.
+        let hash =
+            Multihash::wrap(SHA2_256_TRUNC254_PADDED, &COMMITMENT[..31]).expect("wrap failed");
+        let cid = Cid::new_v1(FIL_COMMITMENT_UNSEALED, hash);
+        assert!(cid_to_data_commitment(&cid).is_err());
+    }
+
+    #[test]
+    fn test_reject_invalid_field_element() {
+        let mut commitment = COMMITMENT;
+        commitment[31] = 0xff;
+
+        assert!(data_commitment_to_cid(&commitment).is_err());
+        assert!(replica_commitment_to_cid(&commitment).is_err());
+
+        let hash = Multihash::wrap(SHA2_256_TRUNC254_PADDED, &commitment).expect("wrap failed");
+        let cid = Cid::new_v1(FIL_COMMITMENT_UNSEALED, hash);
+        assert!(cid_to_data_commitment(&cid).is_err());
+    }
+}
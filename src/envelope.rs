@@ -0,0 +1,182 @@
+//! Self-describing envelopes around vanilla proofs.
+//!
+//! A vanilla proof is only meaningful together with the proof type, sector and challenges it
+//! was generated for. The envelope records these next to the serialized proof, so that a
+//! mismatch is reported before any zk-SNARK work instead of failing during synthesis.
+use std::fmt::Debug;
+
+use anyhow::{ensure, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{Commitment, SectorId};
+
+/// Marks the start of an enveloped vanilla proof.
+const ENVELOPE_MAGIC: [u8; 4] = *b"fvpe";
+
+/// The version of envelopes written by this crate.
+pub const VANILLA_PROOF_ENVELOPE_VERSION: u16 = 1;
+
+/// A vanilla proof together with what it proves.
+///  * `registered_proof` - The proof type the vanilla proof was generated for.
+///  * `sector_id` - The sector proven, for PoSt proofs.
+///  * `partition_index` - The partition proven, for update partition proofs.
+///  * `challenge_digest` - Digest of the inputs the challenges were derived from.
+///  * `proof` - The serialized vanilla proof.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VanillaProofEnvelope<P> {
+    pub registered_proof: P,
+    pub sector_id: Option<SectorId>,
+    pub partition_index: Option<usize>,
+    pub challenge_digest: [u8; 32],
+    pub proof: Vec<u8>,
+}
+
+impl<P> VanillaProofEnvelope<P>
+where
+    P: Copy + Debug + PartialEq + Serialize + DeserializeOwned,
+{
+    /// Serializes the envelope, prefixed with its version.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = ENVELOPE_MAGIC.to_vec();
+        bytes.extend_from_slice(&VANILLA_PROOF_ENVELOPE_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, self)?;
+
+        Ok(bytes)
+    }
+
+    /// Reads an envelope written by [`VanillaProofEnvelope::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let header_len = ENVELOPE_MAGIC.len() + 2;
+        ensure!(
+            bytes.len() >= header_len && bytes[..ENVELOPE_MAGIC.len()] == ENVELOPE_MAGIC,
+            "vanilla proof is not enveloped"
+        );
+        let version = u16::from_le_bytes([bytes[ENVELOPE_MAGIC.len()], bytes[header_len - 1]]);
+        ensure!(
+            version == VANILLA_PROOF_ENVELOPE_VERSION,
+            "unsupported vanilla proof envelope version {}",
+            version
+        );
+
+        Ok(bincode::deserialize(&bytes[header_len..])?)
+    }
+
+    /// Checks that the envelope matches the expected proof type, sector, partition and
+    /// challenges, returning the enveloped proof.
+    pub(crate) fn check(
+        &self,
+        registered_proof: P,
+        sector_id: Option<SectorId>,
+        partition_index: Option<usize>,
+        challenge_digest: &[u8; 32],
+    ) -> Result<&[u8]> {
+        ensure!(
+            self.registered_proof == registered_proof,
+            "vanilla proof was generated for {:?}, expected {:?}",
+            self.registered_proof,
+            registered_proof
+        );
+        ensure!(
+            self.sector_id == sector_id,
+            "vanilla proof was generated for sector {:?}, expected {:?}",
+            self.sector_id,
+            sector_id
+        );
+        ensure!(
+            self.partition_index == partition_index,
+            "vanilla proof was generated for partition {:?}, expected {:?}",
+            self.partition_index,
+            partition_index
+        );
+        ensure!(
+            &self.challenge_digest == challenge_digest,
+            "vanilla proof of sector {:?} was generated for different challenges",
+            self.sector_id
+        );
+
+        Ok(&self.proof)
+    }
+}
+
+/// Digest of the challenges of a PoSt vanilla proof.
+pub(crate) fn post_challenge_digest(challenges: &[u64]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for challenge in challenges {
+        hasher.update(challenge.to_le_bytes());
+    }
+
+    hasher.finalize().into()
+}
+
+/// Digest of the commitments the challenges of an update partition proof are derived from.
+pub(crate) fn update_challenge_digest(
+    comm_r_old: &Commitment,
+    comm_r_new: &Commitment,
+    comm_d_new: &Commitment,
+) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(comm_r_old);
+    hasher.update(comm_r_new);
+    hasher.update(comm_d_new);
+
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::RegisteredPoStProof;
+
+    #[test]
+    fn test_vanilla_proof_envelope() {
+        let registered_proof = RegisteredPoStProof::StackedDrgWindow2KiBV1_2;
+        let digest = post_challenge_digest(&[1, 2, 3]);
+        let envelope = VanillaProofEnvelope {
+            registered_proof,
+            sector_id: Some(SectorId::from(7)),
+            partition_index: None,
+            challenge_digest: digest,
+            proof: vec![4, 5, 6],
+        };
+
+        let bytes = envelope.to_bytes().expect("failed to serialize envelope");
+        let decoded = VanillaProofEnvelope::from_bytes(&bytes).expect("failed to read envelope");
+        assert_eq!(decoded, envelope);
+        assert_eq!(
+            decoded
+                .check(registered_proof, Some(SectorId::from(7)), None, &digest)
+                .expect("envelope rejected"),
+            &[4, 5, 6]
+        );
+
+        let err = decoded
+            .check(registered_proof, Some(SectorId::from(8)), None, &digest)
+            .expect_err("wrong sector accepted");
+        assert!(err.to_string().contains("sector"));
+        assert!(decoded
+            .check(
+                RegisteredPoStProof::StackedDrgWindow2KiBV1,
+                Some(SectorId::from(7)),
+                None,
+                &digest
+            )
+            .is_err());
+        assert!(decoded
+            .check(
+                registered_proof,
+                Some(SectorId::from(7)),
+                None,
+                &post_challenge_digest(&[1, 2])
+            )
+            .is_err());
+
+        assert!(VanillaProofEnvelope::<RegisteredPoStProof>::from_bytes(&[4, 5, 6]).is_err());
+        let mut newer = bytes;
+        newer[ENVELOPE_MAGIC.len()] = 2;
+        let err = VanillaProofEnvelope::<RegisteredPoStProof>::from_bytes(&newer)
+            .expect_err("unknown version accepted");
+        assert!(err.to_string().contains("version 2"));
+    }
+}
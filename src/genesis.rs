@@ -0,0 +1,237 @@
+//! Creation of faux sectors for network genesis.
+//!
+//! Faux sectors skip the replication of their data, but carry a valid `comm_r` and the trees
+//! needed to generate proofs-of-spacetime over them. Only the first sector of a set is
+//! replicated with [`fauxrep`](crate::seal::fauxrep); every further sector shares its replica
+//! and `tree_r_last` through hard links and only gets a fresh `p_aux` from
+//! [`fauxrep2`](crate::seal::fauxrep2).
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::cache_key::CacheKey;
+
+use crate::seal::{compute_comm_d, fauxrep, fauxrep2};
+use crate::{
+    Commitment, PrivateReplicaInfo, ProverId, RegisteredPoStProof, RegisteredSealProof, SectorId,
+};
+
+/// File name of the manifest written by [`create_genesis_sectors`].
+pub const GENESIS_MANIFEST_FILE: &str = "manifest.json";
+
+/// A faux sector listed in a [`GenesisManifest`].
+///  * `sector_id` - ID of the sector.
+///  * `comm_r` - The replica commitment.
+///  * `comm_d` - The data commitment, that of a sector without pieces.
+///  * `cache_dir` - Directory containing the trees of the sector.
+///  * `replica_path` - Path of the replica.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisSector {
+    pub sector_id: SectorId,
+    pub comm_r: Commitment,
+    pub comm_d: Commitment,
+    pub cache_dir: PathBuf,
+    pub replica_path: PathBuf,
+}
+
+/// Description of a set of faux sectors created by [`create_genesis_sectors`].
+///  * `registered_proof` - Seal proof of the sectors.
+///  * `prover_id` - Unique ID of the storage provider owning the sectors.
+///  * `sectors` - The sectors, in order of their IDs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisManifest {
+    pub registered_proof: RegisteredSealProof,
+    pub prover_id: ProverId,
+    pub sectors: Vec<GenesisSector>,
+}
+
+impl GenesisManifest {
+    /// Reads a manifest from the JSON file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("could not open genesis manifest {:?}", path))?;
+
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("could not parse genesis manifest {:?}", path))
+    }
+
+    /// Writes the manifest as JSON to `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .with_context(|| format!("could not create genesis manifest {:?}", path))?;
+
+        serde_json::to_writer_pretty(BufWriter::new(file), self)
+            .with_context(|| format!("could not write genesis manifest {:?}", path))
+    }
+
+    /// Returns the replicas of all sectors, as expected by the PoSt functions.
+    ///
+    /// # Arguments
+    ///
+    /// * `registered_post_proof` - PoSt proof the replicas are used with.
+    pub fn private_replica_infos(
+        &self,
+        registered_post_proof: RegisteredPoStProof,
+    ) -> Result<BTreeMap<SectorId, PrivateReplicaInfo>> {
+        ensure!(
+            registered_post_proof.sector_size() == self.registered_proof.sector_size(),
+            "{:?} does not match the sector size of {:?}",
+            registered_post_proof,
+            self.registered_proof
+        );
+
+        Ok(self
+            .sectors
+            .iter()
+            .map(|sector| {
+                (
+                    sector.sector_id,
+                    PrivateReplicaInfo::new(
+                        registered_post_proof,
+                        sector.comm_r,
+                        sector.cache_dir.clone(),
+                        sector.replica_path.clone(),
+                    ),
+                )
+            })
+            .collect())
+    }
+}
+
+/// Creates `num_sectors` faux sectors with consecutive IDs, starting at `first_sector_id`,
+/// and writes a [`GenesisManifest`] describing them to [`GENESIS_MANIFEST_FILE`] within
+/// `output_dir`.
+///
+/// The replicas are written to `sealed/<sector id>` and the caches to `cache/<sector id>`
+/// below `output_dir`.
+///
+/// # Arguments
+///
+/// * `registered_proof` - Selected seal operation.
+/// * `prover_id` - Unique ID of the storage provider.
+/// * `first_sector_id` - ID of the first sector.
+/// * `num_sectors` - The number of sectors to create.
+/// * `output_dir` - Directory in which the sectors and the manifest are written.
+///
+/// Returns the written [`GenesisManifest`].
+pub fn create_genesis_sectors<P: AsRef<Path>>(
+    registered_proof: RegisteredSealProof,
+    prover_id: ProverId,
+    first_sector_id: SectorId,
+    num_sectors: usize,
+    output_dir: P,
+) -> Result<GenesisManifest> {
+    ensure!(
+        registered_proof.major_version() == 1,
+        "unusupported version"
+    );
+    ensure!(num_sectors > 0, "no sectors requested");
+
+    let output_dir = output_dir.as_ref();
+    let sealed_dir = output_dir.join("sealed");
+    let cache_root = output_dir.join("cache");
+    fs::create_dir_all(&sealed_dir)
+        .with_context(|| format!("could not create directory {:?}", sealed_dir))?;
+    fs::create_dir_all(&cache_root)
+        .with_context(|| format!("could not create directory {:?}", cache_root))?;
+
+    // Faux replicas contain no pieces.
+    let comm_d = compute_comm_d(registered_proof, &[])?;
+
+    let mut sectors: Vec<GenesisSector> = Vec::with_capacity(num_sectors);
+    for i in 0..num_sectors as u64 {
+        let sector_id = SectorId::from(u64::from(first_sector_id) + i);
+        let cache_dir = cache_root.join(u64::from(sector_id).to_string());
+        let replica_path = sealed_dir.join(u64::from(sector_id).to_string());
+        fs::create_dir_all(&cache_dir)
+            .with_context(|| format!("could not create directory {:?}", cache_dir))?;
+
+        let comm_r = match sectors.first() {
+            None => fauxrep(registered_proof, &cache_dir, &replica_path)?,
+            Some(first) => {
+                let p_aux_file = CacheKey::PAux.to_string();
+                link_or_copy(&first.replica_path, &replica_path)?;
+                for entry in fs::read_dir(&first.cache_dir)? {
+                    let entry = entry?;
+                    if entry.file_name().to_str() != Some(p_aux_file.as_str()) {
+                        link_or_copy(&entry.path(), &cache_dir.join(entry.file_name()))?;
+                    }
+                }
+
+                fauxrep2(
+                    registered_proof,
+                    &cache_dir,
+                    first.cache_dir.join(&p_aux_file),
+                )?
+            }
+        };
+
+        sectors.push(GenesisSector {
+            sector_id,
+            comm_r,
+            comm_d,
+            cache_dir,
+            replica_path,
+        });
+    }
+
+    let manifest = GenesisManifest {
+        registered_proof,
+        prover_id,
+        sectors,
+    };
+    manifest.save(output_dir.join(GENESIS_MANIFEST_FILE))?;
+
+    Ok(manifest)
+}
+
+/// Hard links `src` to `dst`, falling back to a copy where linking is not possible.
+fn link_or_copy(src: &Path, dst: &Path) -> Result<()> {
+    if fs::hard_link(src, dst).is_err() {
+        fs::copy(src, dst).with_context(|| format!("could not copy {:?} to {:?}", src, dst))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_genesis_sectors() {
+        let output_dir = std::env::temp_dir().join(format!("genesis-test-{}", std::process::id()));
+        let registered_proof = RegisteredSealProof::StackedDrg2KiBV1_1;
+
+        let manifest = create_genesis_sectors(
+            registered_proof,
+            [7; 32],
+            SectorId::from(100),
+            3,
+            &output_dir,
+        )
+        .expect("failed to create genesis sectors");
+        assert_eq!(manifest.sectors.len(), 3);
+        assert_eq!(manifest.sectors[2].sector_id, SectorId::from(102));
+        assert_ne!(manifest.sectors[0].comm_r, manifest.sectors[1].comm_r);
+
+        let loaded = GenesisManifest::load(output_dir.join(GENESIS_MANIFEST_FILE))
+            .expect("failed to load manifest");
+        assert_eq!(loaded, manifest);
+
+        let replicas = loaded
+            .private_replica_infos(registered_proof.into_window_post())
+            .expect("failed to build replica infos");
+        assert_eq!(replicas.len(), 3);
+        assert!(loaded
+            .private_replica_infos(RegisteredPoStProof::StackedDrgWindow8MiBV1_2)
+            .is_err());
+
+        fs::remove_dir_all(&output_dir).expect("failed to remove output");
+    }
+}
@@ -17,18 +17,30 @@
 //#![warn(clippy::unwrap_used)]
 #![allow(clippy::upper_case_acronyms)]
 
+pub mod commcid;
+pub mod genesis;
+pub mod ni_porep;
+pub mod padding;
 pub mod post;
+pub mod scrub;
 pub mod seal;
 pub mod update;
 
+mod envelope;
 mod registry;
 mod types;
+mod unsealed_cache;
 
+pub use crate::envelope::{VanillaProofEnvelope, VANILLA_PROOF_ENVELOPE_VERSION};
 pub use crate::registry::{
     RegisteredAggregationProof, RegisteredPoStProof, RegisteredSealProof, RegisteredUpdateProof,
 };
-pub use crate::types::{PartitionProofBytes, PrivateReplicaInfo, PublicReplicaInfo};
+pub use crate::types::{
+    PartitionProofBytes, PrivateReplicaInfo, PublicReplicaInfo, UnsealedDataMismatch,
+};
+pub use crate::unsealed_cache::UnsealedSectorCache;
 
+pub use cid;
 pub use filecoin_proofs_v1::types::{
     AggregateSnarkProof, ChallengeSeed, Commitment, PaddedBytesAmount, PartitionSnarkProof,
     PieceInfo, PoStType, ProverId, Ticket, UnpaddedByteIndex, UnpaddedBytesAmount,
@@ -0,0 +1,265 @@
+//! Non-interactive proof of replication.
+//!
+//! Sectors sealed with a `*_Feat_NonInteractivePoRep` proof are committed without waiting
+//! for an interactive seed: the challenges are derived from the replica itself. The helpers
+//! below wrap the generic sealing functions with the conventions these proofs require. A
+//! single sector is proven with [`seal_commit_phase2`], whose partition proofs are aggregated
+//! internally. Several sectors are proven by generating the circuit proofs of each sector
+//! with [`seal_commit_phase2_circuit_proofs`] and aggregating them with
+//! [`aggregate_seal_commit_proofs`].
+use std::path::Path;
+
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::seal::{
+    self, SealCommitPhase1Output, SealCommitPhase2Output, SealPreCommitPhase2Output, SectorSealInfo,
+};
+use crate::{
+    AggregateSnarkProof, ApiFeature, Commitment, PieceInfo, ProverId, RegisteredAggregationProof,
+    RegisteredSealProof, SectorId, Ticket,
+};
+
+/// The seed passed on for non-interactive proofs, whose challenges do not depend on it.
+pub const NI_POREP_SEED: Ticket = [0; 32];
+
+/// The aggregation used for non-interactive proofs.
+pub const NI_POREP_AGGREGATION: RegisteredAggregationProof =
+    RegisteredAggregationProof::SnarkPackV2;
+
+/// The public description of a sector sealed with a non-interactive proof.
+///  * `comm_r` - The replica commitment.
+///  * `comm_d` - The data commitment.
+///  * `prover_id` - Unique ID of the storage provider.
+///  * `sector_id` - ID of the sector, usually relative to the miner.
+///  * `ticket` - The ticket used to generate this sector's replica-id.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NiSectorInfo {
+    pub comm_r: Commitment,
+    pub comm_d: Commitment,
+    pub prover_id: ProverId,
+    pub sector_id: SectorId,
+    pub ticket: Ticket,
+}
+
+impl NiSectorInfo {
+    fn to_seal_info(&self) -> SectorSealInfo {
+        SectorSealInfo {
+            comm_r: self.comm_r,
+            comm_d: self.comm_d,
+            prover_id: self.prover_id,
+            sector_id: self.sector_id,
+            ticket: self.ticket,
+            seed: NI_POREP_SEED,
+        }
+    }
+}
+
+fn ensure_non_interactive(registered_proof: RegisteredSealProof) -> Result<()> {
+    ensure!(
+        registered_proof.major_version() == 1,
+        "unusupported version"
+    );
+    ensure!(
+        registered_proof.feature_enabled(ApiFeature::NonInteractivePoRep),
+        "{:?} is not a non-interactive proof",
+        registered_proof
+    );
+
+    Ok(())
+}
+
+/// Generates the vanilla proofs of a sector sealed with a non-interactive proof. Must be
+/// called with output from [`seal::seal_pre_commit_phase2`].
+///
+/// # Arguments
+///
+/// * `cache_path` - Directory path to use for generation of Merkle tree on disk.
+/// * `replica_path` - out_path from [`seal::seal_pre_commit_phase2`], which points to generated sector replica.
+/// * `prover_id` - Unique ID of the storage provider.
+/// * `sector_id` - ID of the sector, usually relative to the miner.
+/// * `ticket` - The ticket used to generate this sector's replica-id.
+/// * `pre_commit` - Struct returned from [`seal::seal_pre_commit_phase2`].
+/// * `piece_infos` - The piece info (commitment and byte length) for each piece in the sector.
+///
+/// Returns vanilla Merkle tree proof for use by [`seal_commit_phase2`] or
+/// [`seal_commit_phase2_circuit_proofs`].
+pub fn seal_commit_phase1<T: AsRef<Path>>(
+    cache_path: T,
+    replica_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    pre_commit: SealPreCommitPhase2Output,
+    piece_infos: &[PieceInfo],
+) -> Result<SealCommitPhase1Output> {
+    ensure_non_interactive(pre_commit.registered_proof)?;
+
+    seal::seal_commit_phase1(
+        cache_path,
+        replica_path,
+        prover_id,
+        sector_id,
+        ticket,
+        NI_POREP_SEED,
+        pre_commit,
+        piece_infos,
+    )
+}
+
+/// Generates the proof of a single sector, which aggregates all of its partition proofs.
+///
+/// # Arguments
+///
+/// * `phase1_output` - Struct returned from [`seal_commit_phase1`].
+/// * `prover_id` - Unique ID of the storage provider.
+/// * `sector_id` - ID of the sector, usually relative to the miner.
+///
+/// Returns [`SealCommitPhase2Output`] struct containing the proof, to be verified with
+/// [`verify_seal`].
+pub fn seal_commit_phase2(
+    phase1_output: SealCommitPhase1Output,
+    prover_id: ProverId,
+    sector_id: SectorId,
+) -> Result<SealCommitPhase2Output> {
+    ensure_non_interactive(phase1_output.registered_proof)?;
+
+    seal::seal_commit_phase2(phase1_output, prover_id, sector_id)
+}
+
+/// Generates the circuit proofs of a sector, to be aggregated with those of other sectors by
+/// [`aggregate_seal_commit_proofs`].
+///
+/// # Arguments
+///
+/// * `phase1_output` - Struct returned from [`seal_commit_phase1`].
+/// * `sector_id` - ID of the sector, usually relative to the miner.
+///
+/// Returns [`SealCommitPhase2Output`] struct containing the circuit proofs.
+pub fn seal_commit_phase2_circuit_proofs(
+    phase1_output: SealCommitPhase1Output,
+    sector_id: SectorId,
+) -> Result<SealCommitPhase2Output> {
+    ensure_non_interactive(phase1_output.registered_proof)?;
+
+    seal::seal_commit_phase2_circuit_proofs(phase1_output, sector_id)
+}
+
+/// Aggregates the circuit proofs of one or more sectors into a single proof.
+///
+/// # Arguments
+///
+/// * `registered_proof` - Selected seal operation.
+/// * `sectors` - Ordered list of the sectors.
+/// * `commit_outputs` - Ordered list of circuit proofs returned from
+///    [`seal_commit_phase2_circuit_proofs`], one for each sector.
+///
+/// Returns aggregate of zk-SNARK proofs in [`AggregateSnarkProof`].
+pub fn aggregate_seal_commit_proofs(
+    registered_proof: RegisteredSealProof,
+    sectors: &[NiSectorInfo],
+    commit_outputs: &[SealCommitPhase2Output],
+) -> Result<AggregateSnarkProof> {
+    ensure_non_interactive(registered_proof)?;
+    ensure!(
+        sectors.len() == commit_outputs.len(),
+        "expected {} circuit proofs, got {}",
+        sectors.len(),
+        commit_outputs.len()
+    );
+
+    let comm_rs: Vec<Commitment> = sectors.iter().map(|sector| sector.comm_r).collect();
+    let seeds = vec![NI_POREP_SEED; sectors.len()];
+
+    seal::aggregate_seal_commit_proofs(
+        registered_proof,
+        NI_POREP_AGGREGATION,
+        &comm_rs,
+        &seeds,
+        commit_outputs,
+    )
+}
+
+/// Verifies the proof of a single sector, as returned by [`seal_commit_phase2`].
+///
+/// # Arguments
+///
+/// * `registered_proof` - Selected seal operation.
+/// * `sector` - The sector the proof was generated for.
+/// * `proof_vec` - Proof to verify.
+///
+/// Returns result of the proof verification.
+pub fn verify_seal(
+    registered_proof: RegisteredSealProof,
+    sector: &NiSectorInfo,
+    proof_vec: &[u8],
+) -> Result<bool> {
+    ensure_non_interactive(registered_proof)?;
+
+    seal::verify_seal(
+        registered_proof,
+        sector.comm_r,
+        sector.comm_d,
+        sector.prover_id,
+        sector.sector_id,
+        sector.ticket,
+        NI_POREP_SEED,
+        proof_vec,
+    )
+}
+
+/// Verifies an aggregate proof, as returned by [`aggregate_seal_commit_proofs`].
+///
+/// # Arguments
+///
+/// * `registered_proof` - Selected seal operation.
+/// * `aggregate_proof_bytes` - The aggregate proof to verify.
+/// * `sectors` - Ordered list of the sectors, which must match the ordering of the proofs
+///    when aggregated.
+///
+/// Returns true if proof is validated.
+pub fn verify_aggregate_seal_commit_proofs(
+    registered_proof: RegisteredSealProof,
+    aggregate_proof_bytes: AggregateSnarkProof,
+    sectors: &[NiSectorInfo],
+) -> Result<bool> {
+    ensure_non_interactive(registered_proof)?;
+
+    let sectors: Vec<SectorSealInfo> = sectors.iter().map(NiSectorInfo::to_seal_info).collect();
+
+    seal::verify_aggregate_seal_commit_proofs_for_sectors(
+        registered_proof,
+        NI_POREP_AGGREGATION,
+        aggregate_proof_bytes,
+        &sectors,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reject_interactive_proofs() {
+        assert!(ensure_non_interactive(RegisteredSealProof::StackedDrg2KiBV1_1).is_err());
+        assert!(ensure_non_interactive(
+            RegisteredSealProof::StackedDrg2KiBV1_1_Feat_SyntheticPoRep
+        )
+        .is_err());
+        assert!(ensure_non_interactive(
+            RegisteredSealProof::StackedDrg2KiBV1_2_Feat_NonInteractivePoRep
+        )
+        .is_ok());
+
+        let sector = NiSectorInfo {
+            comm_r: [1; 32],
+            comm_d: [2; 32],
+            prover_id: [3; 32],
+            sector_id: SectorId::from(4),
+            ticket: [5; 32],
+        };
+        let err = verify_seal(RegisteredSealProof::StackedDrg2KiBV1_1, &sector, &[])
+            .expect_err("interactive proof accepted");
+        assert!(err.to_string().contains("not a non-interactive proof"));
+    }
+}
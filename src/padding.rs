@@ -0,0 +1,344 @@
+//! Streaming adapters between unpadded user bytes and Fr32 padded sector bytes.
+//!
+//! Fr32 padding operates on blocks of 127 unpadded bytes, each of which is stored as 128
+//! padded bytes (four 254-bit field elements). Offsets in the unpadded space therefore map
+//! onto padded offsets block by block, which is what allows the adapters below to seek
+//! without processing any of the preceding data.
+use std::cmp;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use fr32::Fr32Reader;
+
+use crate::{PaddedBytesAmount, UnpaddedByteIndex, UnpaddedBytesAmount};
+
+/// Number of unpadded bytes in a single Fr32 block.
+const UNPADDED_BLOCK_SIZE: u64 = 127;
+/// Number of padded bytes in a single Fr32 block.
+const PADDED_BLOCK_SIZE: u64 = 128;
+/// Number of blocks which are unpadded at once when reading.
+const READ_BLOCKS: u64 = 64;
+
+/// A reader over Fr32 padded data (e.g. an unsealed sector file), which returns the
+/// unpadded bytes.
+///
+/// All positions, including those passed to [`Seek`], are offsets into the unpadded data.
+/// Only the padded blocks overlapping a read are loaded from the underlying reader.
+#[derive(Debug)]
+pub struct UnpadReader<R> {
+    inner: R,
+    /// Total length of the padded data.
+    padded_len: u64,
+    /// Total length of the unpadded data.
+    unpadded_len: u64,
+    /// Current unpadded position.
+    pos: u64,
+    /// Unpadded bytes of the currently loaded blocks.
+    buf: Vec<u8>,
+    /// Unpadded offset of the first byte in `buf`.
+    buf_start: u64,
+}
+
+impl<R: Read + Seek> UnpadReader<R> {
+    /// Creates a reader over `padded_len` padded bytes, positioned at the first unpadded
+    /// byte. The padded data is expected to start at offset zero of `inner`.
+    pub fn new(inner: R, padded_len: PaddedBytesAmount) -> Self {
+        UnpadReader {
+            inner,
+            padded_len: padded_len.0,
+            unpadded_len: fr32::to_unpadded_bytes(padded_len.0),
+            pos: 0,
+            buf: Vec::new(),
+            buf_start: 0,
+        }
+    }
+
+    /// Returns the number of unpadded bytes which can be read in total.
+    pub fn unpadded_len(&self) -> UnpaddedBytesAmount {
+        UnpaddedBytesAmount(self.unpadded_len)
+    }
+
+    /// Returns the current position in the unpadded data.
+    pub fn position(&self) -> UnpaddedByteIndex {
+        UnpaddedByteIndex(self.pos)
+    }
+
+    /// Consumes the adapter, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Loads the blocks starting with the one containing the current position.
+    fn fill_buf(&mut self) -> io::Result<()> {
+        let block = self.pos / UNPADDED_BLOCK_SIZE;
+        let padded_start = block * PADDED_BLOCK_SIZE;
+        let padded_end = cmp::min(
+            padded_start + READ_BLOCKS * PADDED_BLOCK_SIZE,
+            self.padded_len,
+        );
+
+        let mut padded = vec![0u8; (padded_end - padded_start) as usize];
+        self.inner.seek(SeekFrom::Start(padded_start))?;
+        self.inner.read_exact(&mut padded)?;
+
+        let buf_start = block * UNPADDED_BLOCK_SIZE;
+        let buf_end = cmp::min(
+            buf_start + fr32::to_unpadded_bytes(padded.len() as u64),
+            self.unpadded_len,
+        );
+
+        self.buf.clear();
+        fr32::write_unpadded(&padded, &mut self.buf, 0, (buf_end - buf_start) as usize)?;
+        self.buf_start = buf_start;
+
+        Ok(())
+    }
+
+    fn buffered(&self) -> bool {
+        self.pos >= self.buf_start && self.pos < self.buf_start + self.buf.len() as u64
+    }
+}
+
+impl<R: Read + Seek> Read for UnpadReader<R> {
+    fn read(&mut self, target: &mut [u8]) -> io::Result<usize> {
+        if target.is_empty() || self.pos >= self.unpadded_len {
+            return Ok(0);
+        }
+        if !self.buffered() {
+            self.fill_buf()?;
+        }
+
+        let start = (self.pos - self.buf_start) as usize;
+        let len = cmp::min(target.len(), self.buf.len() - start);
+        target[..len].copy_from_slice(&self.buf[start..start + len]);
+        self.pos += len as u64;
+
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek> Seek for UnpadReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = resolve_seek(pos, self.pos, self.unpadded_len)?;
+
+        Ok(self.pos)
+    }
+}
+
+/// A writer which Fr32 pads the unpadded bytes written to it before passing them on to the
+/// underlying writer.
+///
+/// Data is padded in blocks of 127 bytes. A trailing partial block is only written by
+/// [`PadWriter::finish`], which must be called once all data has been written.
+#[derive(Debug)]
+pub struct PadWriter<W> {
+    inner: W,
+    /// Unpadded bytes of the current, incomplete block.
+    block: Vec<u8>,
+    /// Current unpadded position.
+    pos: u64,
+}
+
+impl<W: Write> PadWriter<W> {
+    /// Creates a writer positioned at the first unpadded byte. The padded data is written
+    /// starting at the current position of `inner`.
+    pub fn new(inner: W) -> Self {
+        PadWriter {
+            inner,
+            block: Vec::with_capacity(UNPADDED_BLOCK_SIZE as usize),
+            pos: 0,
+        }
+    }
+
+    /// Returns the current position in the unpadded data.
+    pub fn position(&self) -> UnpaddedByteIndex {
+        UnpaddedByteIndex(self.pos)
+    }
+
+    /// Pads and writes any buffered partial block, then returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.block.is_empty() {
+            let mut padded = Vec::with_capacity(PADDED_BLOCK_SIZE as usize);
+            Fr32Reader::new(&self.block[..]).read_to_end(&mut padded)?;
+            self.inner.write_all(&padded)?;
+            self.block.clear();
+        }
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        let mut padded = [0u8; PADDED_BLOCK_SIZE as usize];
+        Fr32Reader::new(&self.block[..]).read_exact(&mut padded)?;
+        self.inner.write_all(&padded)?;
+        self.block.clear();
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for PadWriter<W> {
+    fn write(&mut self, source: &[u8]) -> io::Result<usize> {
+        let len = cmp::min(
+            source.len(),
+            UNPADDED_BLOCK_SIZE as usize - self.block.len(),
+        );
+        self.block.extend_from_slice(&source[..len]);
+        self.pos += len as u64;
+
+        if self.block.len() == UNPADDED_BLOCK_SIZE as usize {
+            self.write_block()?;
+        }
+
+        Ok(len)
+    }
+
+    /// Flushes the underlying writer. A buffered partial block is kept, as padding it now
+    /// would change the layout of the data that follows.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write + Seek> Seek for PadWriter<W> {
+    /// Seeks to an unpadded position. As padding operates on whole blocks, this is only
+    /// possible while no partial block is buffered and if the target position is a multiple
+    /// of 127 bytes.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        if !self.block.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot seek while a partial Fr32 block is buffered",
+            ));
+        }
+
+        let padded_len = self.inner.seek(SeekFrom::End(0))?;
+        let unpadded_len = fr32::to_unpadded_bytes(padded_len);
+        let target = resolve_seek(pos, self.pos, unpadded_len)?;
+        if target % UNPADDED_BLOCK_SIZE != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("seek target {} is not aligned to an Fr32 block", target),
+            ));
+        }
+
+        self.inner.seek(SeekFrom::Start(
+            target / UNPADDED_BLOCK_SIZE * PADDED_BLOCK_SIZE,
+        ))?;
+        self.pos = target;
+
+        Ok(self.pos)
+    }
+}
+
+fn resolve_seek(pos: SeekFrom, current: u64, len: u64) -> io::Result<u64> {
+    let target = match pos {
+        SeekFrom::Start(offset) => Some(offset),
+        SeekFrom::End(delta) => checked_add_signed(len, delta),
+        SeekFrom::Current(delta) => checked_add_signed(current, delta),
+    };
+
+    target.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )
+    })
+}
+
+fn checked_add_signed(base: u64, delta: i64) -> Option<u64> {
+    if delta >= 0 {
+        base.checked_add(delta as u64)
+    } else {
+        base.checked_sub(delta.unsigned_abs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    fn unpadded_data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    fn pad(data: &[u8]) -> Vec<u8> {
+        let mut padded = Vec::new();
+        Fr32Reader::new(data)
+            .read_to_end(&mut padded)
+            .expect("failed to pad");
+        padded
+    }
+
+    #[test]
+    fn test_pad_writer_matches_reader() {
+        for len in &[0, 1, 126, 127, 128, 254, 1000, 2032] {
+            let data = unpadded_data(*len);
+
+            let mut writer = PadWriter::new(Vec::new());
+            for chunk in data.chunks(50) {
+                writer.write_all(chunk).expect("failed to write");
+            }
+            let padded = writer.finish().expect("failed to finish");
+
+            assert_eq!(padded, pad(&data), "mismatch for length {}", len);
+        }
+    }
+
+    #[test]
+    fn test_unpad_reader_roundtrip() {
+        let data = unpadded_data(2032);
+        let padded = pad(&data);
+
+        let mut reader =
+            UnpadReader::new(Cursor::new(&padded), PaddedBytesAmount(padded.len() as u64));
+        assert_eq!(reader.unpadded_len(), UnpaddedBytesAmount(2032));
+
+        let mut unpadded = Vec::new();
+        reader.read_to_end(&mut unpadded).expect("failed to read");
+        assert_eq!(unpadded, data);
+    }
+
+    #[test]
+    fn test_unpad_reader_seek() {
+        let data = unpadded_data(16256);
+        let padded = pad(&data);
+
+        let mut reader =
+            UnpadReader::new(Cursor::new(&padded), PaddedBytesAmount(padded.len() as u64));
+
+        for (offset, len) in &[(0, 10), (126, 3), (127, 127), (1000, 5000), (16200, 56)] {
+            reader
+                .seek(SeekFrom::Start(*offset as u64))
+                .expect("failed to seek");
+            let mut buf = vec![0u8; *len];
+            reader.read_exact(&mut buf).expect("failed to read");
+            assert_eq!(&buf[..], &data[*offset..*offset + *len]);
+        }
+
+        let pos = reader.seek(SeekFrom::End(-1)).expect("failed to seek");
+        assert_eq!(pos, data.len() as u64 - 1);
+        assert!(reader
+            .seek(SeekFrom::Current(-(data.len() as i64)))
+            .is_err());
+    }
+
+    #[test]
+    fn test_pad_writer_seek() {
+        let data = unpadded_data(254);
+
+        let mut writer = PadWriter::new(Cursor::new(vec![0u8; 256]));
+        writer
+            .seek(SeekFrom::Start(127))
+            .expect("failed to seek to block boundary");
+        writer.write_all(&data[127..]).expect("failed to write");
+        writer.seek(SeekFrom::Start(0)).expect("failed to seek");
+        writer.write_all(&data[..127]).expect("failed to write");
+        assert!(writer.seek(SeekFrom::Start(10)).is_err());
+
+        let padded = writer.finish().expect("failed to finish").into_inner();
+        assert_eq!(padded, pad(&data));
+    }
+}
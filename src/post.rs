@@ -1,14 +1,23 @@
 //! Generation and verification of Proof-of-Spacetime Merkle tree and zk-SNARK proofs.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
 
-use anyhow::{ensure, Result};
+use anyhow::{anyhow, ensure, Result};
 use filecoin_proofs_v1::with_shape;
+use rayon::prelude::*;
+use storage_proofs_core::cache_key::CacheKey;
 
+use crate::envelope::{post_challenge_digest, VanillaProofEnvelope};
+use crate::scrub::comm_r_matches;
 use crate::types::VanillaProofBytes;
 use crate::{
-    ChallengeSeed, FallbackPoStSectorProof, MerkleTreeTrait, PartitionSnarkProof, PoStType,
-    PrivateReplicaInfo, ProverId, PublicReplicaInfo, RegisteredPoStProof, SectorId, SnarkProof,
+    ApiVersion, ChallengeSeed, FallbackPoStSectorProof, MerkleTreeTrait, PartitionSnarkProof,
+    PoStType, PrivateReplicaInfo, ProverId, PublicReplicaInfo, RegisteredPoStProof, SectorId,
+    SnarkProof,
 };
 
 /// Given some randomness and the length of available sectors, generates a sector challenge.
@@ -109,7 +118,8 @@ fn generate_fallback_sector_challenges_inner<Tree: 'static + MerkleTreeTrait>(
 /// * `replica` - Replica information necessary to generate PoSt.
 /// * `challenges` - Vector of challenges to generate proofs for.
 ///
-/// Returns vanilla proof satisfying challenge.
+/// Returns vanilla proof satisfying challenge, wrapped in a [`VanillaProofEnvelope`] recording
+/// the proof type, the sector and a digest of the challenges.
 pub fn generate_single_vanilla_proof(
     registered_post_proof_type: RegisteredPoStProof,
     sector_id: SectorId,
@@ -160,18 +170,277 @@ fn generate_single_vanilla_proof_inner<Tree: 'static + MerkleTreeTrait>(
             challenges,
         )?;
 
-    let vanilla_proof_bytes_v1: VanillaProofBytes = bincode::serialize(&vanilla_proof)?;
+    let envelope = VanillaProofEnvelope {
+        registered_proof: registered_post_proof_type,
+        sector_id: Some(sector_id),
+        partition_index: None,
+        challenge_digest: post_challenge_digest(challenges),
+        proof: bincode::serialize(&vanilla_proof)?,
+    };
+    let vanilla_proof_bytes_v1: VanillaProofBytes = envelope.to_bytes()?;
 
     Ok(vanilla_proof_bytes_v1)
 }
 
+/// Opens the enveloped vanilla proofs of `generate_*_with_vanilla`, checking that each was
+/// generated for `registered_post_proof_type` and the challenges of its sector. The proofs are
+/// those of the sectors of partition `partition_index` onwards, in order.
+fn open_vanilla_proofs<Tree: 'static + MerkleTreeTrait>(
+    registered_post_proof_type: RegisteredPoStProof,
+    randomness: &ChallengeSeed,
+    prover_id: ProverId,
+    partition_index: usize,
+    vanilla_proofs: &[VanillaProofBytes],
+) -> Result<Vec<FallbackPoStSectorProof<Tree>>> {
+    let envelopes = vanilla_proofs
+        .iter()
+        .map(|proof_bytes| VanillaProofEnvelope::<RegisteredPoStProof>::from_bytes(proof_bytes))
+        .collect::<Result<Vec<_>>>()?;
+
+    let sector_ids = envelopes
+        .iter()
+        .map(|envelope| {
+            envelope
+                .sector_id
+                .ok_or_else(|| anyhow!("vanilla proof is not a PoSt sector proof"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Before V1_2, challenges depend on the position of a sector within the whole sector set.
+    // The sectors of earlier partitions are stood in for by a sector which is not proven here.
+    let mut challenged_sectors = Vec::new();
+    if partition_index > 0 && registered_post_proof_type.version() < ApiVersion::V1_2_0 {
+        let placeholder = (0..)
+            .map(SectorId::from)
+            .find(|sector_id| !sector_ids.contains(sector_id))
+            .expect("sector IDs exhausted");
+        challenged_sectors.resize(
+            partition_index * registered_post_proof_type.sector_count(),
+            placeholder,
+        );
+    }
+    challenged_sectors.extend_from_slice(&sector_ids);
+    let challenges = generate_fallback_sector_challenges_inner::<Tree>(
+        registered_post_proof_type,
+        randomness,
+        &challenged_sectors,
+        prover_id,
+    )?;
+
+    envelopes
+        .iter()
+        .zip(sector_ids)
+        .map(
+            |(envelope, sector_id)| -> Result<FallbackPoStSectorProof<Tree>> {
+                let sector_challenges = challenges
+                    .get(&sector_id)
+                    .ok_or_else(|| anyhow!("no challenges for sector {:?}", sector_id))?;
+                let proof_bytes = envelope.check(
+                    registered_post_proof_type,
+                    Some(sector_id),
+                    None,
+                    &post_challenge_digest(sector_challenges),
+                )?;
+
+                let proof: FallbackPoStSectorProof<Tree> = bincode::deserialize(proof_bytes)?;
+                ensure!(
+                    proof.sector_id == sector_id,
+                    "vanilla proof of sector {:?} is enveloped as sector {:?}",
+                    proof.sector_id,
+                    sector_id
+                );
+
+                Ok(proof)
+            },
+        )
+        .collect()
+}
+
+/// The reason a sector cannot be proven, as found by [`check_provable`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SectorFault {
+    /// The replica is registered for a different PoSt proof type.
+    ProofTypeMismatch(RegisteredPoStProof),
+    /// The replica file is missing, unreadable or of the wrong size.
+    ReplicaUnavailable(String),
+    /// The cache, i.e. `p_aux` or `tree_r_last`, is missing or unreadable.
+    CacheUnavailable(String),
+    /// `comm_r` does not match the commitments stored in `p_aux`.
+    CommRMismatch,
+    /// No valid vanilla proof could be generated for the challenges.
+    VanillaProofFailed(String),
+}
+
+impl fmt::Display for SectorFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SectorFault::ProofTypeMismatch(proof) => {
+                write!(f, "replica is registered for {:?}", proof)
+            }
+            SectorFault::ReplicaUnavailable(reason) => write!(f, "replica unavailable: {}", reason),
+            SectorFault::CacheUnavailable(reason) => write!(f, "cache unavailable: {}", reason),
+            SectorFault::CommRMismatch => write!(f, "comm_r does not match p_aux"),
+            SectorFault::VanillaProofFailed(reason) => {
+                write!(f, "vanilla proof failed: {}", reason)
+            }
+        }
+    }
+}
+
+/// The health of a sector, as found by [`check_provable`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SectorHealth {
+    Healthy,
+    Faulty(SectorFault),
+}
+
+impl SectorHealth {
+    pub fn is_healthy(&self) -> bool {
+        *self == SectorHealth::Healthy
+    }
+}
+
+/// Checks whether each replica can be proven, without generating a zk-SNARK proof.
+///
+/// For each replica, the presence and size of the replica file, the cache and `tree_r_last`
+/// are checked, `comm_r` is checked against `p_aux`, and a vanilla proof is generated for
+/// the challenges of the sector derived from `randomness`.
+///
+/// # Arguments
+/// * `registered_post_proof_type` - Proof type to check for.
+/// * `randomness` - Random seed value for the sampled challenges.
+/// * `replicas` - Replica information for each sector to check.
+/// * `prover_id` - Unique ID of the storage provider.
+///
+/// Returns [`BTreeMap`] of the [`SectorHealth`] of each sector.
+pub fn check_provable(
+    registered_post_proof_type: RegisteredPoStProof,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo>,
+    prover_id: ProverId,
+) -> Result<BTreeMap<SectorId, SectorHealth>> {
+    ensure!(!replicas.is_empty(), "no replicas supplied");
+    ensure!(
+        registered_post_proof_type.major_version() == 1,
+        "only V1 supported"
+    );
+
+    with_shape!(
+        u64::from(registered_post_proof_type.sector_size()),
+        check_provable_inner,
+        registered_post_proof_type,
+        randomness,
+        replicas,
+        prover_id,
+    )
+}
+
+fn check_provable_inner<Tree: 'static + MerkleTreeTrait>(
+    registered_post_proof_type: RegisteredPoStProof,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo>,
+    prover_id: ProverId,
+) -> Result<BTreeMap<SectorId, SectorHealth>> {
+    let config = registered_post_proof_type.as_v1_config();
+    let sector_ids: Vec<SectorId> = replicas.keys().copied().collect();
+    let challenges = generate_fallback_sector_challenges_inner::<Tree>(
+        registered_post_proof_type,
+        randomness,
+        &sector_ids,
+        prover_id,
+    )?;
+
+    let check = |sector_id: SectorId, replica: &PrivateReplicaInfo| -> Result<(), SectorFault> {
+        if replica.registered_proof != registered_post_proof_type {
+            return Err(SectorFault::ProofTypeMismatch(replica.registered_proof));
+        }
+
+        let metadata = fs::metadata(&replica.replica_path)
+            .map_err(|err| SectorFault::ReplicaUnavailable(err.to_string()))?;
+        if metadata.len() != u64::from(config.sector_size) {
+            return Err(SectorFault::ReplicaUnavailable(format!(
+                "size is {} bytes, expected {}",
+                metadata.len(),
+                u64::from(config.sector_size)
+            )));
+        }
+
+        let info_v1 = filecoin_proofs_v1::PrivateReplicaInfo::<Tree>::new(
+            replica.replica_path.clone(),
+            replica.comm_r,
+            replica.cache_dir.clone(),
+        )
+        .map_err(|err| SectorFault::CacheUnavailable(format!("{:#}", err)))?;
+        if !comm_r_matches(&info_v1, &replica.comm_r) {
+            return Err(SectorFault::CommRMismatch);
+        }
+        // Only checks that `tree_r_last` exists: the vanilla proof loads it anyway.
+        if !has_tree_r_last(&replica.cache_dir) {
+            return Err(SectorFault::CacheUnavailable(format!(
+                "no {} in {:?}",
+                CacheKey::CommRLastTree,
+                replica.cache_dir
+            )));
+        }
+
+        let sector_challenges = challenges.get(&sector_id).ok_or_else(|| {
+            SectorFault::VanillaProofFailed("no challenges for sector".to_string())
+        })?;
+        filecoin_proofs_v1::generate_single_vanilla_proof::<Tree>(
+            &config,
+            sector_id,
+            &info_v1,
+            sector_challenges,
+        )
+        .map_err(|err| {
+            // Failing to read the tree is a cache fault, anything else a proof fault.
+            if err.chain().any(|cause| cause.is::<io::Error>()) {
+                SectorFault::CacheUnavailable(format!("{:#}", err))
+            } else {
+                SectorFault::VanillaProofFailed(format!("{:#}", err))
+            }
+        })?;
+
+        Ok(())
+    };
+
+    Ok(replicas
+        .par_iter()
+        .map(|(sector_id, replica)| {
+            let health = match check(*sector_id, replica) {
+                Ok(()) => SectorHealth::Healthy,
+                Err(fault) => SectorHealth::Faulty(fault),
+            };
+
+            (*sector_id, health)
+        })
+        .collect())
+}
+
+/// Whether `cache_dir` contains the files of `tree_r_last`.
+fn has_tree_r_last(cache_dir: &Path) -> bool {
+    let tree_r_last = CacheKey::CommRLastTree.to_string();
+
+    fs::read_dir(cache_dir)
+        .map(|entries| {
+            entries.filter_map(|entry| entry.ok()).any(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .map_or(false, |name| name.contains(&tree_r_last))
+            })
+        })
+        .unwrap_or(false)
+}
+
 /// Generates a Winning Proof-of-Spacetime with provided vanilla Merkle tree proofs.
 ///
 /// # Arguments
 /// * `registered_post_proof_type` - Proof type to generate.
 /// * `randomness` - Random seed value for PoSt challenge.
 /// * `prover_id` - Unique ID of the storage provider.
-/// * `vanilla_proofs` - Vanilla proof for PoSt challenge.
+/// * `vanilla_proofs` - Vanilla proof for PoSt challenge, as returned by
+///    [`generate_single_vanilla_proof`].
 ///
 /// Returns [`SnarkProof`] for challenge.
 pub fn generate_winning_post_with_vanilla(
@@ -201,13 +470,13 @@ fn generate_winning_post_with_vanilla_inner<Tree: 'static + MerkleTreeTrait>(
         "vanilla_proofs cannot be an empty list"
     );
 
-    let fallback_post_sector_proofs: Vec<FallbackPoStSectorProof<Tree>> = vanilla_proofs
-        .iter()
-        .map(|proof_bytes| {
-            let proof: FallbackPoStSectorProof<Tree> = bincode::deserialize(proof_bytes)?;
-            Ok(proof)
-        })
-        .collect::<Result<_>>()?;
+    let fallback_post_sector_proofs = open_vanilla_proofs::<Tree>(
+        registered_post_proof_type,
+        randomness,
+        prover_id,
+        0,
+        vanilla_proofs,
+    )?;
 
     let posts_v1 = filecoin_proofs_v1::generate_winning_post_with_vanilla::<Tree>(
         &registered_post_proof_type.as_v1_config(),
@@ -297,11 +566,78 @@ fn generate_winning_post_inner<Tree: 'static + MerkleTreeTrait>(
     Ok(vec![(registered_proof_v1, posts_v1)])
 }
 
+/// Generates a Winning Proof-of-Spacetime over the sectors challenged out of the full proving
+/// sector set.
+///
+/// This selects the challenged sectors as [`generate_winning_post_sector_challenge`] does,
+/// using their position within `sectors`, and proves them with [`generate_winning_post`].
+///
+/// # Arguments
+/// * `randomness` - Random seed value for PoSt challenge.
+/// * `sectors` - The ordered proving sector set.
+/// * `prover_id` - Unique ID of the storage provider.
+///
+/// Returns [`SnarkProof`] for challenge and the IDs of the challenged sectors, in order of
+/// their challenges.
+pub fn generate_winning_post_from_sector_set(
+    randomness: &ChallengeSeed,
+    sectors: &[(SectorId, PrivateReplicaInfo)],
+    prover_id: ProverId,
+) -> Result<(Vec<(RegisteredPoStProof, SnarkProof)>, Vec<SectorId>)> {
+    ensure!(!sectors.is_empty(), "no sectors supplied");
+    let registered_post_proof_type_v1 = sectors[0].1.registered_proof;
+
+    let challenged = select_winning_post_sectors(
+        registered_post_proof_type_v1,
+        randomness,
+        sectors,
+        prover_id,
+    )?;
+    let sector_ids = challenged.iter().map(|(id, _)| *id).collect();
+    let replicas: BTreeMap<SectorId, PrivateReplicaInfo> =
+        challenged.into_iter().cloned().collect();
+
+    let proof = generate_winning_post(randomness, &replicas, prover_id)?;
+
+    Ok((proof, sector_ids))
+}
+
+/// Returns the entries of `sectors` challenged for a Winning Proof-of-Spacetime, in order of
+/// their challenges.
+fn select_winning_post_sectors<'a, T>(
+    registered_post_proof_type: RegisteredPoStProof,
+    randomness: &ChallengeSeed,
+    sectors: &'a [(SectorId, T)],
+    prover_id: ProverId,
+) -> Result<Vec<&'a (SectorId, T)>> {
+    let mut sector_ids = BTreeSet::new();
+    ensure!(
+        sectors.iter().all(|(id, _)| sector_ids.insert(*id)),
+        "duplicate sectors in the proving sector set"
+    );
+
+    let challenges = generate_winning_post_sector_challenge(
+        registered_post_proof_type,
+        randomness,
+        sectors.len() as u64,
+        prover_id,
+    )?;
+
+    challenges
+        .into_iter()
+        .map(|index| {
+            sectors
+                .get(index as usize)
+                .ok_or_else(|| anyhow!("challenged sector index {} out of range", index))
+        })
+        .collect()
+}
+
 /// Verifies a winning Proof-of-Spacetime.
 ///
 /// The provided `replicas` must be the same ones as passed to `generate_winning_post`, and be based on
 /// the indices generated by `generate_winning_post_sector_challenge`. It is the responsibility of the
-/// caller to ensure this.
+/// caller to ensure this, see [`verify_winning_post_for_sector_set`] for a verification which does.
 ///
 /// # Arguments
 /// * `randomness` - Random seed value for PoSt challenge.
@@ -375,13 +711,49 @@ fn verify_winning_post_inner<Tree: 'static + MerkleTreeTrait>(
     Ok(valid_v1)
 }
 
+/// Verifies a Winning Proof-of-Spacetime against the full proving sector set.
+///
+/// Unlike [`verify_winning_post`], the challenged sectors are derived from `sectors` here, as
+/// [`generate_winning_post_from_sector_set`] does, so a proof over any other sectors fails
+/// to verify.
+///
+/// # Arguments
+/// * `randomness` - Random seed value for PoSt challenge.
+/// * `proof` - Proof to verify.
+/// * `sectors` - The ordered proving sector set.
+/// * `prover_id` - Unique ID of the storage provider.
+///
+/// Returns result of proof verification.
+pub fn verify_winning_post_for_sector_set(
+    randomness: &ChallengeSeed,
+    proof: &[u8],
+    sectors: &[(SectorId, PublicReplicaInfo)],
+    prover_id: ProverId,
+) -> Result<bool> {
+    ensure!(!sectors.is_empty(), "no sectors supplied");
+    let registered_post_proof_type_v1 = sectors[0].1.registered_proof;
+
+    let replicas: BTreeMap<SectorId, PublicReplicaInfo> = select_winning_post_sectors(
+        registered_post_proof_type_v1,
+        randomness,
+        sectors,
+        prover_id,
+    )?
+    .into_iter()
+    .cloned()
+    .collect();
+
+    verify_winning_post(randomness, proof, &replicas, prover_id)
+}
+
 /// Generates a Window Proof-of-Spacetime with provided vanilla Merkle tree proofs.
 ///
 /// # Arguments
 /// * `registered_post_proof_type` - Proof type to generate.
 /// * `randomness` - Random seed value for PoSt challenge.
 /// * `prover_id` - Unique ID of the storage provider.
-/// * `vanilla_proofs` - Vanilla proof for PoSt challenge.
+/// * `vanilla_proofs` - Vanilla proof for PoSt challenge, as returned by
+///    [`generate_single_vanilla_proof`].
 ///
 /// Returns [`SnarkProof`] for challenge.
 pub fn generate_window_post_with_vanilla(
@@ -411,13 +783,13 @@ fn generate_window_post_with_vanilla_inner<Tree: 'static + MerkleTreeTrait>(
         "vanilla_proofs cannot be an empty list"
     );
 
-    let fallback_post_sector_proofs: Vec<FallbackPoStSectorProof<Tree>> = vanilla_proofs
-        .iter()
-        .map(|proof_bytes| {
-            let proof: FallbackPoStSectorProof<Tree> = bincode::deserialize(proof_bytes)?;
-            Ok(proof)
-        })
-        .collect::<Result<_>>()?;
+    let fallback_post_sector_proofs = open_vanilla_proofs::<Tree>(
+        registered_post_proof_type,
+        randomness,
+        prover_id,
+        0,
+        vanilla_proofs,
+    )?;
 
     let posts_v1 = filecoin_proofs_v1::generate_window_post_with_vanilla::<Tree>(
         &registered_post_proof_type.as_v1_config(),
@@ -433,36 +805,44 @@ fn generate_window_post_with_vanilla_inner<Tree: 'static + MerkleTreeTrait>(
 
 /// Generates a Window Proof-of-Spacetime.
 ///
+/// The replicas may be registered with different PoSt proof types, e.g. while sectors move
+/// from one version of window PoSt to the next. A separate proof is generated over the
+/// replicas of each proof type.
+///
 /// # Arguments
 /// * `randomness` - Random seed value for PoSt challenge.
 /// * `replicas` - Replica to generate proof for.
 /// * `prover_id` - Unique ID of the storage provider.
 ///
-/// Returns [`SnarkProof`] for challenge.
+/// Returns a [`SnarkProof`] for each proof type, in order of the first sector of each type.
 pub fn generate_window_post(
     randomness: &ChallengeSeed,
     replicas: &BTreeMap<SectorId, PrivateReplicaInfo>,
     prover_id: ProverId,
 ) -> Result<Vec<(RegisteredPoStProof, SnarkProof)>> {
     ensure!(!replicas.is_empty(), "no replicas supplied");
-    let registered_post_proof_type_v1 = replicas
-        .values()
-        .next()
-        .map(|v| v.registered_proof)
-        .expect("replica map failure");
-    ensure!(
-        registered_post_proof_type_v1.typ() == PoStType::Window,
-        "invalid post type provided"
-    );
 
-    with_shape!(
-        u64::from(registered_post_proof_type_v1.sector_size()),
-        generate_window_post_inner,
-        registered_post_proof_type_v1,
-        randomness,
-        replicas,
-        prover_id,
-    )
+    let mut posts = Vec::new();
+    for (registered_post_proof_type_v1, replicas) in
+        split_by_proof_type(replicas, |info| info.registered_proof)
+    {
+        ensure!(
+            registered_post_proof_type_v1.typ() == PoStType::Window,
+            "invalid post type provided"
+        );
+
+        let proof = with_shape!(
+            u64::from(registered_post_proof_type_v1.sector_size()),
+            generate_window_post_inner,
+            registered_post_proof_type_v1,
+            randomness,
+            &replicas,
+            prover_id,
+        )?;
+        posts.push((registered_post_proof_type_v1, proof));
+    }
+
+    Ok(posts)
 }
 
 fn generate_window_post_inner<Tree: 'static + MerkleTreeTrait>(
@@ -470,7 +850,7 @@ fn generate_window_post_inner<Tree: 'static + MerkleTreeTrait>(
     randomness: &ChallengeSeed,
     replicas: &BTreeMap<SectorId, PrivateReplicaInfo>,
     prover_id: ProverId,
-) -> Result<Vec<(RegisteredPoStProof, SnarkProof)>> {
+) -> Result<SnarkProof> {
     let mut replicas_v1 = BTreeMap::new();
 
     for (id, info) in replicas.iter() {
@@ -495,27 +875,171 @@ fn generate_window_post_inner<Tree: 'static + MerkleTreeTrait>(
     }
 
     ensure!(!replicas_v1.is_empty(), "missing v1 replicas");
-    let posts_v1 = filecoin_proofs_v1::generate_window_post::<Tree>(
+    filecoin_proofs_v1::generate_window_post::<Tree>(
         &registered_proof_v1.as_v1_config(),
         randomness,
         &replicas_v1,
         prover_id,
+    )
+}
+
+/// Splits `replicas` by their PoSt proof type, in order of the first sector of each type.
+fn split_by_proof_type<T: Clone>(
+    replicas: &BTreeMap<SectorId, T>,
+    registered_proof: impl Fn(&T) -> RegisteredPoStProof,
+) -> Vec<(RegisteredPoStProof, BTreeMap<SectorId, T>)> {
+    let mut split: Vec<(RegisteredPoStProof, BTreeMap<SectorId, T>)> = Vec::new();
+    for (id, info) in replicas {
+        let proof_type = registered_proof(info);
+        match split.iter_mut().find(|(typ, _)| *typ == proof_type) {
+            Some((_, replicas)) => {
+                replicas.insert(*id, info.clone());
+            }
+            None => {
+                let mut replicas = BTreeMap::new();
+                replicas.insert(*id, info.clone());
+                split.push((proof_type, replicas));
+            }
+        }
+    }
+
+    split
+}
+
+/// Generates a Window Proof-of-Spacetime over the sectors which can be proven, skipping those
+/// whose vanilla proof fails.
+///
+/// Partitions are proven one after another. Until the first faulty sector, partitions are laid
+/// out as by [`plan_window_post_partitions`] and proven as soon as their vanilla proofs are
+/// generated. The sectors following a faulty sector move to earlier positions, so only the
+/// partitions from the first faulty one onwards are laid out anew, reusing the vanilla proofs
+/// of their healthy sectors. This requires challenges which do not depend on the position of a
+/// sector, i.e. a PoSt proof type of at least [`ApiVersion::V1_2_0`]. The resulting proof
+/// verifies against the replicas without the skipped sectors.
+///
+/// # Arguments
+/// * `randomness` - Random seed value for PoSt challenge.
+/// * `replicas` - Replica to generate proof for.
+/// * `prover_id` - Unique ID of the storage provider.
+///
+/// Returns [`SnarkProof`] for challenge, which is empty if all sectors were skipped, and the
+/// IDs of the skipped sectors.
+pub fn generate_window_post_skipping_faults(
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo>,
+    prover_id: ProverId,
+) -> Result<(Vec<(RegisteredPoStProof, SnarkProof)>, Vec<SectorId>)> {
+    ensure!(!replicas.is_empty(), "no replicas supplied");
+    let registered_post_proof_type_v1 = replicas
+        .values()
+        .next()
+        .map(|v| v.registered_proof)
+        .expect("replica map failure");
+    ensure!(
+        registered_post_proof_type_v1.typ() == PoStType::Window,
+        "invalid post type provided"
+    );
+    ensure!(
+        replicas
+            .values()
+            .all(|replica| replica.registered_proof == registered_post_proof_type_v1),
+        "can only generate the same kind of PoSt"
+    );
+    ensure!(
+        registered_post_proof_type_v1.version() >= ApiVersion::V1_2_0,
+        "faults of {:?} cannot be skipped, its challenges depend on the sector positions",
+        registered_post_proof_type_v1
+    );
+
+    let sector_ids: Vec<SectorId> = replicas.keys().copied().collect();
+    let challenges = generate_fallback_sector_challenges(
+        registered_post_proof_type_v1,
+        randomness,
+        &sector_ids,
+        prover_id,
     )?;
 
-    // once there are multiple versions, merge them before returning
+    let sector_count = registered_post_proof_type_v1.sector_count();
+    let mut partition_proofs = Vec::new();
+    let mut skipped = Vec::new();
+    // Vanilla proofs of the healthy sectors following the first faulty one, in order.
+    let mut relocated = Vec::new();
+    for partition_sectors in sector_ids.chunks(sector_count) {
+        let vanilla_proofs: Vec<(SectorId, Result<VanillaProofBytes>)> = partition_sectors
+            .par_iter()
+            .map(|sector_id| {
+                let proof = challenges
+                    .get(sector_id)
+                    .ok_or_else(|| anyhow!("no challenges for sector {:?}", sector_id))
+                    .and_then(|sector_challenges| {
+                        generate_single_vanilla_proof(
+                            registered_post_proof_type_v1,
+                            *sector_id,
+                            &replicas[sector_id],
+                            sector_challenges,
+                        )
+                    });
+
+                (*sector_id, proof)
+            })
+            .collect();
+
+        if skipped.is_empty() && vanilla_proofs.iter().all(|(_, proof)| proof.is_ok()) {
+            let vanilla_proofs = vanilla_proofs
+                .into_iter()
+                .map(|(_, proof)| proof)
+                .collect::<Result<Vec<_>>>()?;
+            partition_proofs.push(generate_single_window_post_with_vanilla(
+                registered_post_proof_type_v1,
+                randomness,
+                prover_id,
+                &vanilla_proofs,
+                partition_proofs.len(),
+            )?);
+            continue;
+        }
+
+        for (sector_id, proof) in vanilla_proofs {
+            match proof {
+                Ok(proof) => relocated.push(proof),
+                Err(_) => skipped.push(sector_id),
+            }
+        }
+    }
 
-    Ok(vec![(registered_proof_v1, posts_v1)])
+    for vanilla_proofs in relocated.chunks(sector_count) {
+        partition_proofs.push(generate_single_window_post_with_vanilla(
+            registered_post_proof_type_v1,
+            randomness,
+            prover_id,
+            vanilla_proofs,
+            partition_proofs.len(),
+        )?);
+    }
+    if partition_proofs.is_empty() {
+        return Ok((Vec::new(), skipped));
+    }
+
+    let proof =
+        merge_window_post_partition_proofs(registered_post_proof_type_v1, partition_proofs)?;
+
+    Ok((vec![(registered_post_proof_type_v1, proof)], skipped))
 }
 
 /// Verifies a window Proof-of-Spacetime.
 ///
+/// The replicas may be registered with different PoSt proof types, as for
+/// [`generate_window_post`]. There must be exactly one proof for each proof type among the
+/// replicas, which is verified against the replicas of that type, and no proof of any other
+/// type. The order of the proofs does not matter.
+///
 /// # Arguments
 /// * `randomness` - Random seed value for PoSt challenge.
-/// * `proofs` - Proof to verify.
+/// * `proofs` - Proof to verify, one for each proof type of the replicas.
 /// * `replicas` - Replicas to verify proof against.
 /// * `prover_id` - Unique ID of the storage provider which generated the replica.
 ///
-/// Return true if proof verification is successful.
+/// Return true if the verification of all proofs is successful.
 pub fn verify_window_post(
     randomness: &ChallengeSeed,
     proofs: &[(RegisteredPoStProof, &[u8])],
@@ -523,34 +1047,64 @@ pub fn verify_window_post(
     prover_id: ProverId,
 ) -> Result<bool> {
     ensure!(!replicas.is_empty(), "no replicas supplied");
-    ensure!(proofs.len() == 1, "only one version of PoSt supported");
+    ensure!(!proofs.is_empty(), "no proofs supplied");
 
-    let registered_post_proof_type_v1 = proofs[0].0;
+    for (i, (registered_post_proof_type, _)) in proofs.iter().enumerate() {
+        ensure!(
+            proofs[..i]
+                .iter()
+                .all(|(typ, _)| typ != registered_post_proof_type),
+            "more than one proof of {:?} supplied",
+            registered_post_proof_type
+        );
+    }
 
+    let split = split_by_proof_type(replicas, |info| info.registered_proof);
+    let mut proven = Vec::with_capacity(split.len());
+    for (registered_post_proof_type, replicas) in &split {
+        let proof = proofs
+            .iter()
+            .find(|(typ, _)| typ == registered_post_proof_type)
+            .map(|(_, proof)| *proof)
+            .ok_or_else(|| anyhow!("no proof of {:?} supplied", registered_post_proof_type))?;
+        proven.push((*registered_post_proof_type, replicas, proof));
+    }
     ensure!(
-        registered_post_proof_type_v1.typ() == PoStType::Window,
-        "invalid post type provided"
-    );
-    ensure!(
-        registered_post_proof_type_v1.major_version() == 1,
-        "only V1 supported"
+        proofs.len() == split.len(),
+        "proofs supplied for PoSt proof types without replicas"
     );
 
-    with_shape!(
-        u64::from(registered_post_proof_type_v1.sector_size()),
-        verify_window_post_inner,
-        registered_post_proof_type_v1,
-        randomness,
-        proofs,
-        replicas,
-        prover_id,
-    )
+    for (registered_post_proof_type_v1, replicas, proof) in proven {
+        ensure!(
+            registered_post_proof_type_v1.typ() == PoStType::Window,
+            "invalid post type provided"
+        );
+        ensure!(
+            registered_post_proof_type_v1.major_version() == 1,
+            "only V1 supported"
+        );
+
+        let valid = with_shape!(
+            u64::from(registered_post_proof_type_v1.sector_size()),
+            verify_window_post_inner,
+            registered_post_proof_type_v1,
+            randomness,
+            proof,
+            replicas,
+            prover_id,
+        )?;
+        if !valid {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
 }
 
 fn verify_window_post_inner<Tree: 'static + MerkleTreeTrait>(
     registered_proof_v1: RegisteredPoStProof,
     randomness: &ChallengeSeed,
-    proofs: &[(RegisteredPoStProof, &[u8])],
+    proof: &[u8],
     replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
     prover_id: ProverId,
 ) -> Result<bool> {
@@ -571,17 +1125,13 @@ fn verify_window_post_inner<Tree: 'static + MerkleTreeTrait>(
         replicas_v1.insert(*id, info_v1);
     }
 
-    let valid_v1 = filecoin_proofs_v1::verify_window_post::<Tree>(
+    filecoin_proofs_v1::verify_window_post::<Tree>(
         &registered_proof_v1.as_v1_config(),
         randomness,
         &replicas_v1,
         prover_id,
-        proofs[0].1,
-    )?;
-
-    // once there are multiple versions, merge them before returning
-
-    Ok(valid_v1)
+        proof,
+    )
 }
 
 /// For window PoSt only, gets the number of partitions based on proof type.
@@ -610,6 +1160,100 @@ pub fn get_num_partition_for_fallback_post(
     ))
 }
 
+/// The sectors of a single window PoSt partition, see [`plan_window_post_partitions`].
+///  * `partition_index` - Index of the partition.
+///  * `sector_ids` - The sectors of the partition, in the order in which their vanilla proofs
+///    are passed to [`generate_single_window_post_with_vanilla`].
+///  * `challenges` - The challenges of each sector of the partition.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowPoStPartition {
+    pub partition_index: usize,
+    pub sector_ids: Vec<SectorId>,
+    pub challenges: BTreeMap<SectorId, Vec<u64>>,
+}
+
+/// For window PoSt only, splits a set of sectors into partitions, as expected by the verifier.
+///
+/// Sectors are assigned to partitions in ascending order of their IDs, filling each partition
+/// up to the sector count of the proof type. Each partition can be proven independently, by
+/// generating the vanilla proofs of its sectors for their challenges and passing them to
+/// [`generate_single_window_post_with_vanilla`].
+///
+/// # Arguments
+/// * `registered_post_proof_v1` - PoSt proof type.
+/// * `randomness` - Random seed value for PoSt challenge.
+/// * `pub_sectors` - Sector IDs to prove.
+/// * `prover_id` - Unique ID of the storage provider.
+///
+/// Returns the [`WindowPoStPartition`] of each partition, in order.
+pub fn plan_window_post_partitions(
+    registered_post_proof_v1: RegisteredPoStProof,
+    randomness: &ChallengeSeed,
+    pub_sectors: &[SectorId],
+    prover_id: ProverId,
+) -> Result<Vec<WindowPoStPartition>> {
+    ensure!(
+        registered_post_proof_v1.typ() == PoStType::Window,
+        "invalid post type provided"
+    );
+    ensure!(
+        registered_post_proof_v1.major_version() == 1,
+        "only V1 supported"
+    );
+
+    let sector_ids: Vec<SectorId> = pub_sectors
+        .iter()
+        .copied()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    ensure!(
+        sector_ids.len() == pub_sectors.len(),
+        "duplicate sectors supplied"
+    );
+
+    let mut challenges = generate_fallback_sector_challenges(
+        registered_post_proof_v1,
+        randomness,
+        &sector_ids,
+        prover_id,
+    )?;
+    let num_partitions =
+        get_num_partition_for_fallback_post(registered_post_proof_v1, sector_ids.len())?;
+
+    let partitions = sector_ids
+        .chunks(registered_post_proof_v1.sector_count())
+        .enumerate()
+        .map(
+            |(partition_index, sector_ids)| -> Result<WindowPoStPartition> {
+                let challenges = sector_ids
+                    .iter()
+                    .map(|sector_id| {
+                        let sector_challenges = challenges.remove(sector_id).ok_or_else(|| {
+                            anyhow!("missing challenges for sector {:?}", sector_id)
+                        })?;
+                        Ok((*sector_id, sector_challenges))
+                    })
+                    .collect::<Result<_>>()?;
+
+                Ok(WindowPoStPartition {
+                    partition_index,
+                    sector_ids: sector_ids.to_vec(),
+                    challenges,
+                })
+            },
+        )
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(
+        partitions.len() == num_partitions,
+        "expected {} partitions, planned {}",
+        num_partitions,
+        partitions.len()
+    );
+
+    Ok(partitions)
+}
+
 /// Merge multiple window PoSt partition proofs into a single zk-SNARK proof.
 ///
 /// # Arguments
@@ -640,13 +1284,13 @@ fn generate_single_window_post_with_vanilla_inner<Tree: 'static + MerkleTreeTrai
     vanilla_proofs: &[VanillaProofBytes],
     partition_index: usize,
 ) -> Result<PartitionSnarkProof> {
-    let fallback_post_sector_proofs: Vec<FallbackPoStSectorProof<Tree>> = vanilla_proofs
-        .iter()
-        .map(|proof_bytes| {
-            let proof: FallbackPoStSectorProof<Tree> = bincode::deserialize(proof_bytes)?;
-            Ok(proof)
-        })
-        .collect::<Result<_>>()?;
+    let fallback_post_sector_proofs = open_vanilla_proofs::<Tree>(
+        registered_post_proof_v1,
+        randomness,
+        prover_id,
+        partition_index,
+        vanilla_proofs,
+    )?;
 
     filecoin_proofs_v1::generate_single_window_post_with_vanilla(
         &registered_post_proof_v1.as_v1_config(),
@@ -693,3 +1337,403 @@ pub fn generate_single_window_post_with_vanilla(
         partition_index,
     )
 }
+
+fn verify_window_post_partition_inner<Tree: 'static + MerkleTreeTrait>(
+    registered_post_proof_v1: RegisteredPoStProof,
+    randomness: &ChallengeSeed,
+    partition_replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+    prover_id: ProverId,
+    partition_proof: &PartitionSnarkProof,
+) -> Result<bool> {
+    let replicas_v1 = partition_replicas
+        .iter()
+        .map(|(id, info)| {
+            ensure!(
+                info.registered_proof == registered_post_proof_v1,
+                "can only verify the same kind of PoSt"
+            );
+            Ok((
+                *id,
+                filecoin_proofs_v1::PublicReplicaInfo::new(info.comm_r)?,
+            ))
+        })
+        .collect::<Result<BTreeMap<_, _>>>()?;
+
+    // A single partition verifies as the only partition of a proof over its own sectors.
+    filecoin_proofs_v1::verify_window_post::<Tree>(
+        &registered_post_proof_v1.as_v1_config(),
+        randomness,
+        &replicas_v1,
+        prover_id,
+        &partition_proof.0,
+    )
+}
+
+/// Verifies the Window Proof-of-Spacetime of a single partition, as returned by
+/// [`generate_single_window_post_with_vanilla`], before it is merged with
+/// [`merge_window_post_partition_proofs`].
+///
+/// With `V1_2` proofs the challenges of a sector do not depend on its position, so any
+/// partition can be verified on its own. With `V1` proofs they do, and only the first
+/// partition can be verified on its own.
+///
+/// # Arguments
+/// * `registered_post_proof_v1` - PoSt proof type.
+/// * `randomness` - Random seed value for PoSt challenge.
+/// * `partition_index` - Index of the partition within the full proof.
+/// * `partition_replicas` - Replicas of the sectors of the partition, see
+///    [`plan_window_post_partitions`].
+/// * `prover_id` - Unique ID of the storage provider which generated the replica.
+/// * `partition_proof` - Proof of the partition to verify.
+///
+/// Return true if proof verification is successful.
+pub fn verify_window_post_partition(
+    registered_post_proof_v1: RegisteredPoStProof,
+    randomness: &ChallengeSeed,
+    partition_index: usize,
+    partition_replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+    prover_id: ProverId,
+    partition_proof: &PartitionSnarkProof,
+) -> Result<bool> {
+    ensure!(
+        registered_post_proof_v1.typ() == PoStType::Window,
+        "invalid post type provided"
+    );
+    ensure!(
+        registered_post_proof_v1.major_version() == 1,
+        "only V1 supported"
+    );
+    ensure!(!partition_replicas.is_empty(), "no replicas supplied");
+    ensure!(
+        partition_replicas.len() <= registered_post_proof_v1.sector_count(),
+        "{} replicas exceed the {} sectors of a partition",
+        partition_replicas.len(),
+        registered_post_proof_v1.sector_count()
+    );
+    ensure!(
+        partition_index == 0 || registered_post_proof_v1.version() >= ApiVersion::V1_2_0,
+        "only the first partition of {:?} can be verified on its own",
+        registered_post_proof_v1
+    );
+
+    with_shape!(
+        u64::from(registered_post_proof_v1.sector_size()),
+        verify_window_post_partition_inner,
+        registered_post_proof_v1,
+        randomness,
+        partition_replicas,
+        prover_id,
+        partition_proof,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use filecoin_proofs_v1::SectorShape2KiB;
+
+    use crate::seal::fauxrep;
+    use crate::RegisteredSealProof;
+
+    #[test]
+    fn test_check_provable() {
+        let dir = std::env::temp_dir().join(format!("check-provable-{}", std::process::id()));
+        let registered_proof = RegisteredSealProof::StackedDrg2KiBV1_1;
+        let registered_post_proof = registered_proof.into_window_post();
+
+        let faux_replica = |name: &str| -> (PathBuf, PathBuf, [u8; 32]) {
+            let cache_dir = dir.join(name).join("cache");
+            let replica_path = dir.join(name).join("sealed");
+            fs::create_dir_all(&cache_dir).expect("failed to create cache dir");
+            let comm_r = fauxrep(registered_proof, &cache_dir, &replica_path)
+                .expect("failed to create replica");
+            (cache_dir, replica_path, comm_r)
+        };
+
+        let mut replicas = BTreeMap::new();
+        let (cache_dir, replica_path, comm_r) = faux_replica("healthy");
+        replicas.insert(
+            SectorId::from(1),
+            PrivateReplicaInfo::new(registered_post_proof, comm_r, cache_dir, replica_path),
+        );
+        let (cache_dir, replica_path, comm_r) = faux_replica("missing");
+        fs::remove_file(&replica_path).expect("failed to remove replica");
+        replicas.insert(
+            SectorId::from(2),
+            PrivateReplicaInfo::new(registered_post_proof, comm_r, cache_dir, replica_path),
+        );
+        let (cache_dir, replica_path, _) = faux_replica("wrong-comm-r");
+        replicas.insert(
+            SectorId::from(3),
+            PrivateReplicaInfo::new(registered_post_proof, [1; 32], cache_dir, replica_path),
+        );
+
+        let (cache_dir, replica_path, comm_r) = faux_replica("no-tree");
+        for entry in fs::read_dir(&cache_dir).expect("failed to read cache dir") {
+            let path = entry.expect("failed to read entry").path();
+            if path.to_string_lossy().contains("tree-r-last") {
+                fs::remove_file(&path).expect("failed to remove tree");
+            }
+        }
+        replicas.insert(
+            SectorId::from(4),
+            PrivateReplicaInfo::new(registered_post_proof, comm_r, cache_dir, replica_path),
+        );
+
+        let health = check_provable(registered_post_proof, &[5; 32], &replicas, [6; 32])
+            .expect("failed to check replicas");
+        assert!(health[&SectorId::from(1)].is_healthy());
+        assert!(matches!(
+            health[&SectorId::from(2)],
+            SectorHealth::Faulty(SectorFault::ReplicaUnavailable(_))
+        ));
+        assert_eq!(
+            health[&SectorId::from(3)],
+            SectorHealth::Faulty(SectorFault::CommRMismatch)
+        );
+        assert!(matches!(
+            health[&SectorId::from(4)],
+            SectorHealth::Faulty(SectorFault::CacheUnavailable(_))
+        ));
+
+        fs::remove_dir_all(&dir).expect("failed to remove test dir");
+    }
+
+    #[test]
+    fn test_open_vanilla_proofs_of_partition() {
+        let dir = std::env::temp_dir().join(format!("open-vanilla-{}", std::process::id()));
+        let registered_proof = RegisteredSealProof::StackedDrg2KiBV1_1;
+        let registered_post_proof = RegisteredPoStProof::StackedDrgWindow2KiBV1;
+        let randomness = [5; 32];
+        let prover_id = [6; 32];
+
+        // The last sector is the only one of the second partition.
+        let sector_count = registered_post_proof.sector_count();
+        let sector_ids: Vec<SectorId> = (0..=sector_count as u64).map(SectorId::from).collect();
+        let last = sector_ids[sector_count];
+        let cache_dir = dir.join("cache");
+        let replica_path = dir.join("sealed");
+        fs::create_dir_all(&cache_dir).expect("failed to create cache dir");
+        let comm_r =
+            fauxrep(registered_proof, &cache_dir, &replica_path).expect("failed to create replica");
+        let replica =
+            PrivateReplicaInfo::new(registered_post_proof, comm_r, cache_dir, replica_path);
+
+        let challenges = generate_fallback_sector_challenges(
+            registered_post_proof,
+            &randomness,
+            &sector_ids,
+            prover_id,
+        )
+        .expect("failed to generate challenges");
+        let vanilla_proof = generate_single_vanilla_proof(
+            registered_post_proof,
+            last,
+            &replica,
+            &challenges[&last],
+        )
+        .expect("failed to generate vanilla proof");
+
+        let proofs = open_vanilla_proofs::<SectorShape2KiB>(
+            registered_post_proof,
+            &randomness,
+            prover_id,
+            1,
+            &[vanilla_proof.clone()],
+        )
+        .expect("vanilla proof of the second partition rejected");
+        assert_eq!(proofs[0].sector_id, last);
+
+        // Challenges of V1 proofs depend on the partition of the sector.
+        assert!(open_vanilla_proofs::<SectorShape2KiB>(
+            registered_post_proof,
+            &randomness,
+            prover_id,
+            0,
+            &[vanilla_proof],
+        )
+        .is_err());
+
+        fs::remove_dir_all(&dir).expect("failed to remove test dir");
+    }
+
+    #[test]
+    fn test_generate_window_post_skipping_faults_requires_v1_2() {
+        let mut replicas = BTreeMap::new();
+        replicas.insert(
+            SectorId::from(1),
+            PrivateReplicaInfo::new(
+                RegisteredPoStProof::StackedDrgWindow2KiBV1,
+                [1; 32],
+                PathBuf::from("missing-cache"),
+                PathBuf::from("missing-replica"),
+            ),
+        );
+
+        let err = generate_window_post_skipping_faults(&[2; 32], &replicas, [3; 32])
+            .expect_err("V1 faults skipped");
+        assert!(err.to_string().contains("cannot be skipped"));
+    }
+
+    #[test]
+    fn test_plan_window_post_partitions() {
+        let registered_post_proof = RegisteredPoStProof::StackedDrgWindow2KiBV1_2;
+        let sector_count = registered_post_proof.sector_count();
+        let num_sectors = 2 * sector_count + 1;
+        let pub_sectors: Vec<SectorId> =
+            (0..num_sectors as u64).rev().map(SectorId::from).collect();
+
+        let partitions =
+            plan_window_post_partitions(registered_post_proof, &[1; 32], &pub_sectors, [2; 32])
+                .expect("failed to plan partitions");
+        assert_eq!(partitions.len(), 3);
+        assert_eq!(
+            partitions[2].sector_ids,
+            vec![SectorId::from(num_sectors as u64 - 1)]
+        );
+
+        let challenges = generate_fallback_sector_challenges(
+            registered_post_proof,
+            &[1; 32],
+            &pub_sectors,
+            [2; 32],
+        )
+        .expect("failed to generate challenges");
+        let mut next_sector = 0;
+        for (index, partition) in partitions.iter().enumerate() {
+            assert_eq!(partition.partition_index, index);
+            for sector_id in &partition.sector_ids {
+                assert_eq!(*sector_id, SectorId::from(next_sector));
+                assert_eq!(partition.challenges[sector_id], challenges[sector_id]);
+                next_sector += 1;
+            }
+        }
+
+        let duplicates = vec![SectorId::from(1), SectorId::from(1)];
+        assert!(
+            plan_window_post_partitions(registered_post_proof, &[1; 32], &duplicates, [2; 32])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_select_winning_post_sectors() {
+        let registered_post_proof = RegisteredPoStProof::StackedDrgWinning2KiBV1;
+        let sectors: Vec<(SectorId, u64)> = (0..10).map(|i| (SectorId::from(100 + i), i)).collect();
+
+        let challenged =
+            select_winning_post_sectors(registered_post_proof, &[3; 32], &sectors, [4; 32])
+                .expect("failed to select sectors");
+        let indices = generate_winning_post_sector_challenge(
+            registered_post_proof,
+            &[3; 32],
+            sectors.len() as u64,
+            [4; 32],
+        )
+        .expect("failed to generate challenge");
+        assert_eq!(challenged.len(), indices.len());
+        for ((sector_id, index), challenged_index) in challenged.into_iter().zip(indices) {
+            assert_eq!(*index, challenged_index);
+            assert_eq!(*sector_id, SectorId::from(100 + challenged_index));
+        }
+
+        let duplicates = vec![(SectorId::from(1), 0), (SectorId::from(1), 1)];
+        assert!(
+            select_winning_post_sectors(registered_post_proof, &[3; 32], &duplicates, [4; 32])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_verify_window_post_proof_types() {
+        let mut replicas = BTreeMap::new();
+        replicas.insert(
+            SectorId::from(1),
+            PublicReplicaInfo::new(RegisteredPoStProof::StackedDrgWindow2KiBV1_2, [1; 32]),
+        );
+        replicas.insert(
+            SectorId::from(2),
+            PublicReplicaInfo::new(RegisteredPoStProof::StackedDrgWindow2KiBV1, [2; 32]),
+        );
+        replicas.insert(
+            SectorId::from(3),
+            PublicReplicaInfo::new(RegisteredPoStProof::StackedDrgWindow2KiBV1_2, [3; 32]),
+        );
+
+        let split = split_by_proof_type(&replicas, |info| info.registered_proof);
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[0].0, RegisteredPoStProof::StackedDrgWindow2KiBV1_2);
+        assert_eq!(
+            split[0].1.keys().copied().collect::<Vec<_>>(),
+            vec![SectorId::from(1), SectorId::from(3)]
+        );
+        assert_eq!(split[1].0, RegisteredPoStProof::StackedDrgWindow2KiBV1);
+
+        let proof: &[u8] = &[0; 192];
+        let missing = [(RegisteredPoStProof::StackedDrgWindow2KiBV1_2, proof)];
+        let err = verify_window_post(&[4; 32], &missing, &replicas, [5; 32])
+            .expect_err("missing proof accepted");
+        assert!(err.to_string().contains("no proof of"));
+
+        let duplicate = [
+            (RegisteredPoStProof::StackedDrgWindow2KiBV1_2, proof),
+            (RegisteredPoStProof::StackedDrgWindow2KiBV1, proof),
+            (RegisteredPoStProof::StackedDrgWindow2KiBV1_2, proof),
+        ];
+        let err = verify_window_post(&[4; 32], &duplicate, &replicas, [5; 32])
+            .expect_err("duplicate proof accepted");
+        assert!(err.to_string().contains("more than one proof"));
+
+        let extra = [
+            (RegisteredPoStProof::StackedDrgWindow2KiBV1_2, proof),
+            (RegisteredPoStProof::StackedDrgWindow2KiBV1, proof),
+            (RegisteredPoStProof::StackedDrgWindow8MiBV1_2, proof),
+        ];
+        assert!(verify_window_post(&[4; 32], &extra, &replicas, [5; 32]).is_err());
+    }
+
+    #[test]
+    fn test_verify_window_post_partition_preconditions() {
+        let proof = PartitionSnarkProof(vec![0; 192]);
+        let replicas = |registered_post_proof, num_sectors: u64| {
+            (0..num_sectors)
+                .map(|i| {
+                    (
+                        SectorId::from(i),
+                        PublicReplicaInfo::new(registered_post_proof, [1; 32]),
+                    )
+                })
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        let registered_post_proof = RegisteredPoStProof::StackedDrgWindow2KiBV1;
+        let err = verify_window_post_partition(
+            registered_post_proof,
+            &[2; 32],
+            1,
+            &replicas(registered_post_proof, 1),
+            [3; 32],
+            &proof,
+        )
+        .expect_err("later V1 partition accepted");
+        assert!(err.to_string().contains("only the first partition"));
+
+        let registered_post_proof = RegisteredPoStProof::StackedDrgWindow2KiBV1_2;
+        let too_many = replicas(
+            registered_post_proof,
+            registered_post_proof.sector_count() as u64 + 1,
+        );
+        assert!(verify_window_post_partition(
+            registered_post_proof,
+            &[2; 32],
+            1,
+            &too_many,
+            [3; 32],
+            &proof
+        )
+        .is_err());
+    }
+}
@@ -1,5 +1,6 @@
 //! Data types used for Proof-of-Replication and Proof-of-Spacetime
 
+use std::cmp;
 use std::collections::HashMap;
 use std::path::PathBuf;
 
@@ -946,6 +947,59 @@ impl RegisteredUpdateProof {
     }
 }
 
+/// Largest number of SNARK proofs in a single aggregate supported by SnarkPack.
+const MAX_AGGREGATED_SNARK_PROOFS: usize = 8192;
+
+/// Smallest number of sectors in an aggregate accepted on chain, as defined by FIP-0013.
+const MIN_AGGREGATED_SECTORS: usize = 4;
+
+/// Largest number of sectors in an aggregate accepted on chain, as defined by FIP-0013.
+const MAX_AGGREGATED_SECTORS: usize = 819;
+
+/// Smallest number of NI-PoRep sectors in an aggregate accepted on chain.
+const MIN_AGGREGATED_SECTORS_NI: usize = 1;
+
+/// Largest number of NI-PoRep sectors in an aggregate accepted on chain.
+const MAX_AGGREGATED_SECTORS_NI: usize = 65;
+
+impl RegisteredAggregationProof {
+    /// Returns the minimum number of SNARK proofs in an aggregate. Aggregates are padded to a
+    /// power of two, so fewer proofs are padded up to this count.
+    pub fn min_snark_proofs(self) -> usize {
+        match self {
+            RegisteredAggregationProof::SnarkPackV1 | RegisteredAggregationProof::SnarkPackV2 => 2,
+        }
+    }
+
+    /// Returns the maximum number of SNARK proofs in an aggregate, including padding.
+    pub fn max_snark_proofs(self) -> usize {
+        match self {
+            RegisteredAggregationProof::SnarkPackV1 | RegisteredAggregationProof::SnarkPackV2 => {
+                MAX_AGGREGATED_SNARK_PROOFS
+            }
+        }
+    }
+
+    /// Returns the minimum and maximum number of sectors sealed with `registered_proof`, which
+    /// can be aggregated into a single proof accepted on chain. Each sector contributes one
+    /// SNARK proof per partition, so the maximum is also bounded by
+    /// [`RegisteredAggregationProof::max_snark_proofs`].
+    pub fn sector_limits(self, registered_proof: RegisteredSealProof) -> (usize, usize) {
+        let partitions = usize::from(registered_proof.partitions());
+        let (min_sectors, max_sectors) =
+            if registered_proof.feature_enabled(ApiFeature::NonInteractivePoRep) {
+                (MIN_AGGREGATED_SECTORS_NI, MAX_AGGREGATED_SECTORS_NI)
+            } else {
+                (MIN_AGGREGATED_SECTORS, MAX_AGGREGATED_SECTORS)
+            };
+
+        (
+            min_sectors,
+            cmp::min(max_sectors, self.max_snark_proofs() / partitions),
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
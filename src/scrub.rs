@@ -0,0 +1,352 @@
+//! Detection of silent data corruption in sealed replicas.
+//!
+//! A replica is scrubbed by generating the same Merkle inclusion proofs a Proof-of-Spacetime
+//! needs for a set of challenged nodes. The rows of `tree_r_last` which are not kept on disk
+//! are rebuilt from the replica for every proof, so a proof reads the whole segment of the
+//! replica below the lowest kept row, see [`scrub_segment_nodes`]. The upper rows are read from
+//! `tree_r_last`, and the proof is checked against the `comm_r_last` stored in `p_aux`, which in
+//! turn is checked against `comm_r`.
+use std::ops::Range;
+
+use anyhow::{ensure, Result};
+use filecoin_hashers::{Domain, HashFunction, Hasher};
+use filecoin_proofs_v1::with_shape;
+use storage_proofs_core::merkle::get_base_tree_count;
+use storage_proofs_core::util::default_rows_to_discard;
+
+use crate::{
+    Commitment, MerkleTreeTrait, PrivateReplicaInfo, RegisteredPoStProof, SectorId, NODE_SIZE,
+};
+
+/// Arity of the base tree of `tree_r_last`, which is the same for all sector shapes.
+const BASE_ARITY: u64 = 8;
+
+/// Number of segments which are proven together, before falling back to proving them one by
+/// one.
+const SCRUB_BATCH_SIZE: usize = 64;
+
+/// Selects the nodes checked by [`scrub_replica`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScrubSelection {
+    /// Checks pseudo-randomly chosen segments, derived from `seed`.
+    Sample { seed: [u8; 32] },
+    /// Checks every segment in order, starting with the one containing `start_node`. Resuming
+    /// a sweep at the [`ScrubReport::next_node`] of the previous run eventually covers the
+    /// whole replica.
+    Sweep { start_node: u64 },
+}
+
+/// The outcome of [`scrub_replica`].
+///  * `checked_nodes` - The number of nodes which were checked, i.e. of the checked segments.
+///  * `bytes_read` - The number of bytes of the replica read to check them.
+///  * `comm_r_valid` - Whether `comm_r` matches the commitments stored in `p_aux`.
+///  * `corrupted` - The ranges of nodes which could not be proven, in ascending order.
+///  * `next_node` - The node at which to resume a sweep, `None` once the sweep is complete.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScrubReport {
+    pub checked_nodes: u64,
+    pub bytes_read: u64,
+    pub comm_r_valid: bool,
+    pub corrupted: Vec<Range<u64>>,
+    pub next_node: Option<u64>,
+}
+
+/// Returns the number of nodes in a segment of a replica of `registered_post_proof_type`.
+///
+/// Proving any node rebuilds the discarded rows of `tree_r_last` above its segment from the
+/// replica, so a corrupted node fails the proofs of all nodes in its segment, and checking a
+/// node reads its whole segment.
+pub fn scrub_segment_nodes(registered_post_proof_type: RegisteredPoStProof) -> Result<u64> {
+    ensure!(
+        registered_post_proof_type.major_version() == 1,
+        "unusupported version"
+    );
+
+    with_shape!(
+        u64::from(registered_post_proof_type.sector_size()),
+        scrub_segment_nodes_inner,
+        registered_post_proof_type,
+    )
+}
+
+fn scrub_segment_nodes_inner<Tree: 'static + MerkleTreeTrait>(
+    registered_post_proof_type: RegisteredPoStProof,
+) -> Result<u64> {
+    let num_nodes = u64::from(registered_post_proof_type.sector_size()) / NODE_SIZE as u64;
+    let base_tree_leaves = num_nodes / get_base_tree_count::<Tree>() as u64;
+    let rows_to_discard =
+        default_rows_to_discard(base_tree_leaves as usize, BASE_ARITY as usize) as u32;
+
+    Ok(BASE_ARITY.pow(rows_to_discard + 1).min(base_tree_leaves))
+}
+
+/// Checks segments of a sealed replica against `tree_r_last` and `comm_r`, reading at most
+/// `max_bytes` bytes of the replica.
+///
+/// Every checked segment, see [`scrub_segment_nodes`], is proven by a single challenge, so a
+/// corrupted node is reported as the range of its segment. Segments which cannot be read are
+/// reported as corrupted as well, as they cannot be proven either.
+///
+/// # Arguments
+/// * `registered_post_proof_type` - PoSt proof type of the replica.
+/// * `sector_id` - ID of the sector.
+/// * `replica` - The replica to scrub.
+/// * `selection` - How the checked segments are selected.
+/// * `max_bytes` - The I/O budget of this run.
+///
+/// Returns a [`ScrubReport`] of the checked nodes.
+pub fn scrub_replica(
+    registered_post_proof_type: RegisteredPoStProof,
+    sector_id: SectorId,
+    replica: &PrivateReplicaInfo,
+    selection: ScrubSelection,
+    max_bytes: u64,
+) -> Result<ScrubReport> {
+    ensure!(
+        registered_post_proof_type.major_version() == 1,
+        "unusupported version"
+    );
+    ensure!(
+        replica.registered_proof == registered_post_proof_type,
+        "can only scrub replicas of the same kind of PoSt"
+    );
+
+    let num_nodes = u64::from(registered_post_proof_type.sector_size()) / NODE_SIZE as u64;
+    let segment_nodes = scrub_segment_nodes(registered_post_proof_type)?;
+    let num_segments = num_nodes / segment_nodes;
+    let budget_segments = max_bytes / (segment_nodes * NODE_SIZE as u64);
+
+    let (challenges, next_node) = match selection {
+        ScrubSelection::Sample { seed } => {
+            let mut rng = SplitMix64::new(&seed);
+            let challenges = (0..budget_segments.min(num_segments))
+                .map(|_| rng.next_u64() % num_segments * segment_nodes)
+                .collect();
+
+            (challenges, None)
+        }
+        ScrubSelection::Sweep { start_node } => {
+            ensure!(
+                start_node < num_nodes,
+                "start node {} exceeds the {} nodes of the sector",
+                start_node,
+                num_nodes
+            );
+            let first_segment = start_node / segment_nodes;
+            let end_segment = num_segments.min(first_segment + budget_segments);
+            let challenges = (first_segment..end_segment)
+                .map(|segment| segment * segment_nodes)
+                .collect();
+            let next_node = Some(end_segment * segment_nodes).filter(|&node| node < num_nodes);
+
+            (challenges, next_node)
+        }
+    };
+
+    with_shape!(
+        u64::from(registered_post_proof_type.sector_size()),
+        scrub_replica_inner,
+        registered_post_proof_type,
+        sector_id,
+        replica,
+        challenges,
+        segment_nodes,
+        next_node,
+    )
+}
+
+fn scrub_replica_inner<Tree: 'static + MerkleTreeTrait>(
+    registered_post_proof_type: RegisteredPoStProof,
+    sector_id: SectorId,
+    replica: &PrivateReplicaInfo,
+    challenges: Vec<u64>,
+    segment_nodes: u64,
+    next_node: Option<u64>,
+) -> Result<ScrubReport> {
+    let config = registered_post_proof_type.as_v1_config();
+    let info_v1 = filecoin_proofs_v1::PrivateReplicaInfo::<Tree>::new(
+        replica.replica_path.clone(),
+        replica.comm_r,
+        replica.cache_dir.clone(),
+    )?;
+
+    let comm_r_valid = comm_r_matches(&info_v1, &replica.comm_r);
+
+    let prove = |challenges: &[u64]| {
+        filecoin_proofs_v1::generate_single_vanilla_proof::<Tree>(
+            &config, sector_id, &info_v1, challenges,
+        )
+        .is_ok()
+    };
+
+    let mut corrupted_segments = Vec::new();
+    for batch in challenges.chunks(SCRUB_BATCH_SIZE) {
+        if prove(batch) {
+            continue;
+        }
+        for &challenge in batch {
+            if !prove(&[challenge]) {
+                corrupted_segments.push(challenge / segment_nodes);
+            }
+        }
+    }
+    corrupted_segments.sort_unstable();
+    corrupted_segments.dedup();
+
+    let mut corrupted: Vec<Range<u64>> = Vec::new();
+    for segment in corrupted_segments {
+        let start = segment * segment_nodes;
+        match corrupted.last_mut() {
+            Some(range) if range.end == start => range.end = start + segment_nodes,
+            _ => corrupted.push(start..start + segment_nodes),
+        }
+    }
+
+    let checked_nodes = challenges.len() as u64 * segment_nodes;
+    Ok(ScrubReport {
+        checked_nodes,
+        bytes_read: checked_nodes * NODE_SIZE as u64,
+        comm_r_valid,
+        corrupted,
+        next_node,
+    })
+}
+
+/// Checks that `comm_r` is the commitment to the `comm_c` and `comm_r_last` stored in the
+/// `p_aux` of a replica.
+pub(crate) fn comm_r_matches<Tree: 'static + MerkleTreeTrait>(
+    info_v1: &filecoin_proofs_v1::PrivateReplicaInfo<Tree>,
+    comm_r: &Commitment,
+) -> bool {
+    let expected = <<Tree::Hasher as Hasher>::Function as HashFunction<_>>::hash2(
+        &info_v1.safe_comm_c(),
+        &info_v1.safe_comm_r_last(),
+    );
+
+    expected.into_bytes()[..] == comm_r[..]
+}
+
+/// A small pseudo-random generator for sampling nodes. Scrubbing does not need
+/// unpredictable challenges, only a spread over the replica.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn new(seed: &[u8; 32]) -> Self {
+        let state = seed.chunks(8).fold(0u64, |state, chunk| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(chunk);
+            state.rotate_left(17) ^ u64::from_le_bytes(bytes)
+        });
+
+        SplitMix64(state)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{self, OpenOptions};
+    use std::io::{Seek, SeekFrom, Write};
+
+    use crate::seal::fauxrep;
+    use crate::RegisteredSealProof;
+
+    #[test]
+    fn test_scrub_replica() {
+        let dir = std::env::temp_dir().join(format!("scrub-test-{}", std::process::id()));
+        let cache_dir = dir.join("cache");
+        let replica_path = dir.join("sealed");
+        fs::create_dir_all(&cache_dir).expect("failed to create cache dir");
+
+        let registered_proof = RegisteredSealProof::StackedDrg2KiBV1_1;
+        let registered_post_proof = registered_proof.into_window_post();
+        let comm_r =
+            fauxrep(registered_proof, &cache_dir, &replica_path).expect("failed to create replica");
+        let replica = PrivateReplicaInfo::new(
+            registered_post_proof,
+            comm_r,
+            cache_dir.clone(),
+            replica_path.clone(),
+        );
+        let sector_id = SectorId::from(1);
+        let sweep = ScrubSelection::Sweep { start_node: 0 };
+
+        // A 2KiB tree keeps only its root, so the whole sector is a single segment.
+        let segment_nodes =
+            scrub_segment_nodes(registered_post_proof).expect("failed to get segment size");
+        assert_eq!(segment_nodes, 64);
+        let segment_bytes = segment_nodes * NODE_SIZE as u64;
+
+        let report = scrub_replica(registered_post_proof, sector_id, &replica, sweep, u64::MAX)
+            .expect("failed to scrub replica");
+        assert!(report.comm_r_valid);
+        assert!(report.corrupted.is_empty());
+        assert_eq!(report.checked_nodes, 64);
+        assert_eq!(report.bytes_read, segment_bytes);
+        assert_eq!(report.next_node, None);
+
+        // The budget limits a sweep, which resumes where it stopped.
+        let report = scrub_replica(
+            registered_post_proof,
+            sector_id,
+            &replica,
+            sweep,
+            segment_bytes - 1,
+        )
+        .expect("failed to scrub replica");
+        assert_eq!(report.checked_nodes, 0);
+        assert_eq!(report.bytes_read, 0);
+        assert_eq!(report.next_node, Some(0));
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(&replica_path)
+            .expect("failed to open replica");
+        file.seek(SeekFrom::Start(20 * NODE_SIZE as u64))
+            .expect("failed to seek");
+        file.write_all(&[1]).expect("failed to corrupt replica");
+        drop(file);
+
+        let report = scrub_replica(registered_post_proof, sector_id, &replica, sweep, u64::MAX)
+            .expect("failed to scrub replica");
+        assert_eq!(report.corrupted, vec![0..64]);
+
+        let sample = ScrubSelection::Sample { seed: [3; 32] };
+        let report = scrub_replica(
+            registered_post_proof,
+            sector_id,
+            &replica,
+            sample,
+            4 * segment_bytes,
+        )
+        .expect("failed to scrub replica");
+        assert_eq!(report.checked_nodes, 64);
+        assert_eq!(report.corrupted, vec![0..64]);
+
+        fs::remove_dir_all(&dir).expect("failed to remove test dir");
+    }
+
+    #[test]
+    fn test_scrub_segment_nodes() {
+        // Production trees discard two rows above the base, rebuilding 8^3 nodes.
+        assert_eq!(
+            scrub_segment_nodes(RegisteredPoStProof::StackedDrgWindow32GiBV1_2)
+                .expect("failed to get segment size"),
+            512
+        );
+        assert_eq!(
+            scrub_segment_nodes(RegisteredPoStProof::StackedDrgWinning64GiBV1)
+                .expect("failed to get segment size"),
+            512
+        );
+    }
+}
@@ -1,9 +1,15 @@
 //! Proof-of-Replication for sealing, unsealing, and verifying data sectors
+use std::cmp;
 use std::convert::TryInto;
-use std::io::{Read, Seek, Write};
+use std::io::{self, Read, Seek, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 
-use anyhow::{bail, ensure, Error, Result};
+use anyhow::{anyhow, bail, ensure, Error, Result};
 use blstrs::Scalar as Fr;
 
 use filecoin_proofs_v1::constants::{
@@ -13,14 +19,16 @@ use filecoin_proofs_v1::constants::{
     SECTOR_SIZE_32_GIB, SECTOR_SIZE_32_KIB, SECTOR_SIZE_4_KIB, SECTOR_SIZE_512_MIB,
     SECTOR_SIZE_64_GIB, SECTOR_SIZE_8_MIB,
 };
+use filecoin_proofs_v1::pieces::get_piece_start_byte;
 use filecoin_proofs_v1::types::{MerkleTreeTrait, VanillaSealProof as RawVanillaSealProof};
-use filecoin_proofs_v1::{with_shape, Labels as RawLabels};
+use filecoin_proofs_v1::{with_shape, Labels as RawLabels, SectorSize};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    AggregateSnarkProof, AggregateVersion, ApiFeature, Commitment, Hasher, PieceInfo, ProverId,
-    RegisteredAggregationProof, RegisteredSealProof, SectorId, Ticket, UnpaddedByteIndex,
-    UnpaddedBytesAmount,
+    AggregateSnarkProof, AggregateVersion, ApiFeature, Commitment, Hasher, PaddedBytesAmount,
+    PieceInfo, ProverId, RegisteredAggregationProof, RegisteredSealProof, SectorId, Ticket,
+    UnpaddedByteIndex, UnpaddedBytesAmount, UnsealedDataMismatch, NODE_SIZE,
 };
 
 /// The output of [`seal_pre_commit_phase1`].
@@ -330,6 +338,34 @@ pub struct SealCommitPhase2Output {
     pub proof: Vec<u8>,
 }
 
+/// An aggregate proof of a subset of sectors, see [`aggregate_seal_commit_proofs_planned`].
+///  * `sector_indices` - Indices of the aggregated sectors into the inputs, in the order in
+///    which their proofs were aggregated.
+///  * `proof` - The aggregate proof.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlannedAggregate {
+    pub sector_indices: Vec<usize>,
+    pub proof: AggregateSnarkProof,
+}
+
+/// The public description of a sealed sector, from which the public inputs of its seal proof
+/// are derived.
+///  * `comm_r` - The replica commitment.
+///  * `comm_d` - The data commitment.
+///  * `prover_id` - Unique ID of the storage provider.
+///  * `sector_id` - ID of the sector, usually relative to the miner.
+///  * `ticket` - The ticket used to generate this sector's replica-id.
+///  * `seed` - The seed used to derive the porep challenges.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SectorSealInfo {
+    pub comm_r: Commitment,
+    pub comm_d: Commitment,
+    pub prover_id: ProverId,
+    pub sector_id: SectorId,
+    pub ticket: Ticket,
+    pub seed: Ticket,
+}
+
 /// Ensure that any persisted cached data is discarded.
 ///
 /// # Arguments
@@ -1092,6 +1128,120 @@ pub fn aggregate_seal_commit_proofs_inner<Tree: 'static + MerkleTreeTrait>(
     )
 }
 
+/// Splits `num_sectors` sectors into consecutive groups, each of which can be aggregated into
+/// a single proof. The groups respect the sector limits of `registered_aggregation` and are
+/// chosen to minimize the number of padding proofs, and then the number of aggregates.
+///
+/// # Arguments
+///
+/// * `registered_proof` - Selected seal operation.
+/// * `registered_aggregation` - Aggregation proof types.
+/// * `num_sectors` - The number of sectors to aggregate.
+///
+/// Returns the ranges of sector indices to aggregate together, in order.
+pub fn plan_seal_commit_aggregation(
+    registered_proof: RegisteredSealProof,
+    registered_aggregation: RegisteredAggregationProof,
+    num_sectors: usize,
+) -> Result<Vec<Range<usize>>> {
+    ensure!(num_sectors > 0, "cannot aggregate with empty outputs");
+
+    let partitions = usize::from(registered_proof.partitions());
+    let (min_sectors, max_sectors) = registered_aggregation.sector_limits(registered_proof);
+    ensure!(
+        min_sectors <= max_sectors,
+        "proofs of {:?} cannot be aggregated",
+        registered_proof
+    );
+    let padding = |sectors: usize| {
+        let proofs = sectors * partitions;
+        cmp::max(
+            registered_aggregation.min_snark_proofs(),
+            proofs.next_power_of_two(),
+        ) - proofs
+    };
+
+    // The best plan for the first `n` sectors, as (padding, aggregates, size of last aggregate).
+    let mut best: Vec<Option<(usize, usize, usize)>> = vec![None; num_sectors + 1];
+    best[0] = Some((0, 0, 0));
+    for n in 1..=num_sectors {
+        for size in min_sectors..=cmp::min(n, max_sectors) {
+            if let Some((prev_padding, prev_aggregates, _)) = best[n - size] {
+                let candidate = (prev_padding + padding(size), prev_aggregates + 1, size);
+                let better = match best[n] {
+                    Some((padding, aggregates, _)) => {
+                        (candidate.0, candidate.1) < (padding, aggregates)
+                    }
+                    None => true,
+                };
+                if better {
+                    best[n] = Some(candidate);
+                }
+            }
+        }
+    }
+
+    let mut ranges = Vec::new();
+    let mut end = num_sectors;
+    while end > 0 {
+        let (_, _, size) =
+            best[end].ok_or_else(|| anyhow!("cannot aggregate {} sectors", num_sectors))?;
+        ranges.push(end - size..end);
+        end -= size;
+    }
+    ranges.reverse();
+
+    Ok(ranges)
+}
+
+/// Aggregates any number of seal proofs, splitting them into as many aggregates as needed
+/// according to [`plan_seal_commit_aggregation`].
+///
+/// # Arguments
+///
+/// * `registered_proof` - Selected seal operation.
+/// * `registered_aggregation` - Aggregation proof types.
+/// * `comm_rs` - Ordered list of sector replica commitments.
+/// * `seeds` - Ordered list of seeds used to derive the PoRep challenges.
+/// * `commit_outputs` - Ordered list of seal proof outputs returned from [`seal_commit_phase2`].
+///
+/// Returns the aggregates in [`PlannedAggregate`] structs, together with the sectors each of
+/// them contains.
+pub fn aggregate_seal_commit_proofs_planned(
+    registered_proof: RegisteredSealProof,
+    registered_aggregation: RegisteredAggregationProof,
+    comm_rs: &[Commitment],
+    seeds: &[Ticket],
+    commit_outputs: &[SealCommitPhase2Output],
+) -> Result<Vec<PlannedAggregate>> {
+    ensure!(
+        comm_rs.len() == commit_outputs.len() && seeds.len() == commit_outputs.len(),
+        "inconsistent inputs"
+    );
+
+    plan_seal_commit_aggregation(
+        registered_proof,
+        registered_aggregation,
+        commit_outputs.len(),
+    )?
+    .into_iter()
+    .map(|range| {
+        let proof = aggregate_seal_commit_proofs(
+            registered_proof,
+            registered_aggregation,
+            &comm_rs[range.clone()],
+            &seeds[range.clone()],
+            &commit_outputs[range.clone()],
+        )?;
+
+        Ok(PlannedAggregate {
+            sector_indices: range.collect(),
+            proof,
+        })
+    })
+    .collect()
+}
+
 /// Given a `porep_config`, an aggregate proof, a list of seeds and a combined and flattened list
 /// of public inputs, this method verifies the aggregate seal proof.
 ///
@@ -1163,6 +1313,63 @@ pub fn verify_aggregate_seal_commit_proofs_inner<Tree: 'static + MerkleTreeTrait
     )
 }
 
+/// Verifies an aggregate seal proof of the given sectors. Unlike
+/// [`verify_aggregate_seal_commit_proofs`], the public inputs are derived from the sector
+/// descriptions, in parallel, and combined in the order of the sectors.
+///
+/// # Arguments
+///
+/// * `registered_proof` - Selected seal operation.
+/// * `registered_aggregation` - Aggregation proof types.
+/// * `aggregate_proof_bytes` - The returned aggregate proof from [`aggregate_seal_commit_proofs`].
+/// * `sectors` - Ordered list of the sectors, which must match the ordering of the seal proofs
+///    when aggregated.
+///
+/// Returns true if proof is validated.
+pub fn verify_aggregate_seal_commit_proofs_for_sectors(
+    registered_proof: RegisteredSealProof,
+    registered_aggregation: RegisteredAggregationProof,
+    aggregate_proof_bytes: AggregateSnarkProof,
+    sectors: &[SectorSealInfo],
+) -> Result<bool> {
+    ensure!(
+        registered_proof.major_version() == 1,
+        "unusupported version"
+    );
+    ensure!(
+        !sectors.is_empty(),
+        "cannot verify an aggregate of no sectors"
+    );
+
+    let sector_inputs = sectors
+        .par_iter()
+        .map(|sector| {
+            get_seal_inputs(
+                registered_proof,
+                sector.comm_r,
+                sector.comm_d,
+                sector.prover_id,
+                sector.sector_id,
+                sector.ticket,
+                sector.seed,
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let commit_inputs: Vec<Vec<Fr>> = sector_inputs.into_iter().flatten().collect();
+
+    let comm_rs: Vec<Commitment> = sectors.iter().map(|sector| sector.comm_r).collect();
+    let seeds: Vec<Ticket> = sectors.iter().map(|sector| sector.seed).collect();
+
+    verify_aggregate_seal_commit_proofs(
+        registered_proof,
+        registered_aggregation,
+        aggregate_proof_bytes,
+        &comm_rs,
+        &seeds,
+        commit_inputs,
+    )
+}
+
 // Special case implementation of porep sealing which does not depend on slow sealing,
 // intended to be used at chain genesis.
 ///
@@ -1366,10 +1573,127 @@ pub fn verify_batch_seal(
     )
 }
 
+/// Verify multiple proofs of sealed sectors, returning a result for each proof. Each input
+/// argument is an ordered vector corresponding to the proof to verify.
+///
+/// Proofs with an invalid length or all zero commitments are rejected up front. The remaining
+/// proofs are verified as one batch, which is only bisected to find the invalid proofs if the
+/// batch fails to verify.
+///
+/// # Arguments
+/// * `registered_proof` - Selected seal operation.
+/// * `comm_r_ins` - comm_r replica commitment from seal operation.
+/// * `comm_d_in` - comm_d data commitment from seal operation.
+/// * `prover_id` - Unique ID of the storage provider.
+/// * `sector_id` - ID of the sector, usually relative to the miner.
+/// * `ticket` - The ticket used to generate this sector's replica-id.
+/// * `seed` - The seed used to derive the porep challenges.
+/// * `proof_vec` - Proofs to verify.
+///
+/// Returns the result of the verification of each proof, in input order. Proofs which could
+/// not be verified at all, e.g. due to a format error, have an error result. An error of the
+/// batch verification itself, e.g. due to missing parameters, is returned as is.
+pub fn verify_batch_seal_each(
+    registered_proof: RegisteredSealProof,
+    comm_r_ins: &[Commitment],
+    comm_d_ins: &[Commitment],
+    prover_ids: &[ProverId],
+    sector_ids: &[SectorId],
+    tickets: &[Ticket],
+    seeds: &[Ticket],
+    proof_vecs: &[&[u8]],
+) -> Result<Vec<Result<bool>>> {
+    ensure!(
+        registered_proof.major_version() == 1,
+        "unusupported version"
+    );
+
+    let len = comm_r_ins.len();
+    ensure!(
+        comm_d_ins.len() == len
+            && prover_ids.len() == len
+            && sector_ids.len() == len
+            && tickets.len() == len
+            && seeds.len() == len
+            && proof_vecs.len() == len,
+        "inconsistent inputs"
+    );
+
+    let proof_len =
+        usize::from(registered_proof.partitions()) * registered_proof.single_partition_proof_len();
+    let mut results: Vec<Result<bool>> = (0..len)
+        .map(|i| {
+            if proof_vecs[i].len() != proof_len {
+                Err(anyhow!(
+                    "invalid proof length {}, expected {}",
+                    proof_vecs[i].len(),
+                    proof_len
+                ))
+            } else if comm_r_ins[i] == [0; 32] {
+                Err(anyhow!("invalid all zero commitment (comm_r)"))
+            } else if comm_d_ins[i] == [0; 32] {
+                Err(anyhow!("invalid all zero commitment (comm_d)"))
+            } else {
+                Ok(true)
+            }
+        })
+        .collect();
+
+    let verify = |indices: &[usize]| -> Result<bool> {
+        let gather = |values: &[Commitment]| -> Vec<Commitment> {
+            indices.iter().map(|&i| values[i]).collect()
+        };
+        let sector_ids: Vec<SectorId> = indices.iter().map(|&i| sector_ids[i]).collect();
+        let proof_vecs: Vec<&[u8]> = indices.iter().map(|&i| proof_vecs[i]).collect();
+
+        verify_batch_seal(
+            registered_proof,
+            &gather(comm_r_ins),
+            &gather(comm_d_ins),
+            &gather(prover_ids),
+            &sector_ids,
+            &gather(tickets),
+            &gather(seeds),
+            &proof_vecs,
+        )
+    };
+
+    let indices: Vec<usize> = (0..len).filter(|&i| results[i].is_ok()).collect();
+    bisect_batch(&indices, &verify, &mut results)?;
+
+    Ok(results)
+}
+
+/// Verifies the proofs at `indices` as one batch, splitting the batch in halves until the
+/// proofs which fail to verify are isolated. Stops at the first verification error.
+fn bisect_batch<F>(indices: &[usize], verify: &F, results: &mut [Result<bool>]) -> Result<()>
+where
+    F: Fn(&[usize]) -> Result<bool>,
+{
+    if indices.is_empty() {
+        return Ok(());
+    }
+
+    // Errors are not caused by invalid proofs, and would recur in every half.
+    let valid = verify(indices)?;
+    if valid || indices.len() == 1 {
+        for &i in indices {
+            results[i] = Ok(valid);
+        }
+    } else {
+        let (left, right) = indices.split_at(indices.len() / 2);
+        bisect_batch(left, verify, results)?;
+        bisect_batch(right, verify, results)?;
+    }
+
+    Ok(())
+}
+
 /// Unseals the sector at `sealed_path` and returns the bytes for a piece
 /// whose first (unpadded) byte begins at `offset` and ends at `offset` plus
 /// `num_bytes`, inclusive. Note that the entire sector is unsealed each time
-/// this function is called.
+/// this function is called, use an [`UnsealedSectorCache`](crate::UnsealedSectorCache) to
+/// share one unsealing across repeated reads of the same sector.
 ///
 /// # Arguments
 ///
@@ -1780,6 +2104,332 @@ pub fn unseal_range<T: Into<PathBuf> + AsRef<Path>, R: Read, W: Write>(
     }
 }
 
+/// Number of chunks the unsealing thread may buffer ahead of an [`UnsealedRangeReader`].
+const UNSEALED_READER_CHANNEL_BOUND: usize = 16;
+
+/// A reader over an unsealed range of a sealed sector, returned by [`unseal_range_reader`].
+///
+/// Unsealing runs on a background thread, which hands the unsealed bytes over as they are
+/// written and blocks while too many chunks are waiting to be read. An error during unsealing
+/// is returned by the read that would have returned the missing data.
+///
+/// The whole sector is unsealed before the first byte is handed over. Dropping the reader
+/// stops the background thread at the next read of the sealed sector or hand-over of data,
+/// but an unsealing which is already in progress runs to completion first. The reader does
+/// not wait for the thread when it is dropped.
+pub struct UnsealedRangeReader {
+    receiver: Receiver<Vec<u8>>,
+    handle: Option<JoinHandle<Result<UnpaddedBytesAmount>>>,
+    cancelled: Arc<AtomicBool>,
+    chunk: Vec<u8>,
+    chunk_offset: usize,
+}
+
+impl UnsealedRangeReader {
+    /// Waits for the unsealing thread and converts its result into an I/O result.
+    fn join(&mut self) -> io::Result<()> {
+        match self.handle.take() {
+            Some(handle) => match handle.join() {
+                Ok(Ok(_)) => Ok(()),
+                Ok(Err(err)) => Err(io::Error::new(io::ErrorKind::Other, err)),
+                Err(_) => Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "unsealing thread panicked",
+                )),
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+impl Read for UnsealedRangeReader {
+    fn read(&mut self, target: &mut [u8]) -> io::Result<usize> {
+        if target.is_empty() {
+            return Ok(0);
+        }
+
+        while self.chunk_offset == self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.chunk_offset = 0;
+                }
+                // The sending side is gone, so unsealing has finished.
+                Err(_) => {
+                    self.join()?;
+                    return Ok(0);
+                }
+            }
+        }
+
+        let len = std::cmp::min(target.len(), self.chunk.len() - self.chunk_offset);
+        target[..len].copy_from_slice(&self.chunk[self.chunk_offset..self.chunk_offset + len]);
+        self.chunk_offset += len;
+
+        Ok(len)
+    }
+}
+
+impl Drop for UnsealedRangeReader {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Reads the sealed sector for an [`UnsealedRangeReader`], failing once it is dropped.
+struct CancellableReader<R> {
+    inner: R,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<R: Read> Read for CancellableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            // Not `Interrupted`, which `read_to_end` would retry forever.
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "unsealed range reader dropped",
+            ));
+        }
+
+        self.inner.read(buf)
+    }
+}
+
+/// Forwards everything written to it to an [`UnsealedRangeReader`].
+struct ChannelWriter(SyncSender<Vec<u8>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        self.0.send(buf.to_vec()).map_err(|_| {
+            io::Error::new(io::ErrorKind::BrokenPipe, "unsealed range reader dropped")
+        })?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Unseals the sector read from `sealed_sector` and returns a reader over the bytes of a
+/// piece whose first (unpadded) byte begins at `offset` and ends at `offset` plus
+/// `num_bytes`, exclusive. This is the streaming counterpart of [`unseal_range`]: instead of
+/// writing to a sink, the unsealed bytes are produced in the background and handed out as
+/// the returned reader is consumed. Note that the entire sector is unsealed for each reader.
+///
+/// # Arguments
+///
+/// * `registered_proof` - Selected seal operation.
+/// * `cache_path` - Path to the directory in which the sector data's Merkle tree is written.
+/// * `sealed_sector` - A byte source from which we read sealed sector data.
+/// * `prover_id` - Unique ID of the storage provider.
+/// * `sector_id` - ID of the sector, usually relative to the miner.
+/// * `comm_d` - The commitment to the sector's data.
+/// * `ticket` - The ticket that was used to generate the sector's replica-id.
+/// * `offset` - The byte index in the unsealed sector of the first byte that we want to read.
+/// * `num_bytes` - The number of bytes that we want to read.
+///
+/// Returns an [`UnsealedRangeReader`] over the requested range.
+pub fn unseal_range_reader<T, R>(
+    registered_proof: RegisteredSealProof,
+    cache_path: T,
+    sealed_sector: R,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+    ticket: Ticket,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+) -> Result<UnsealedRangeReader>
+where
+    T: Into<PathBuf> + AsRef<Path>,
+    R: Read + Send + 'static,
+{
+    ensure!(
+        registered_proof.major_version() == 1,
+        "unusupported version"
+    );
+
+    let sector_bytes =
+        UnpaddedBytesAmount::from(PaddedBytesAmount::from(registered_proof.sector_size()));
+    ensure!(
+        offset.0 + num_bytes.0 <= sector_bytes.0,
+        "range {}..{} exceeds unsealed sector size {}",
+        offset.0,
+        offset.0 + num_bytes.0,
+        sector_bytes.0
+    );
+
+    let cache_path: PathBuf = cache_path.into();
+    let (sender, receiver) = sync_channel(UNSEALED_READER_CHANNEL_BOUND);
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let sealed_sector = CancellableReader {
+        inner: sealed_sector,
+        cancelled: cancelled.clone(),
+    };
+
+    let handle = thread::Builder::new()
+        .name(format!("unseal-range-{}", u64::from(sector_id)))
+        .spawn(move || {
+            unseal_range(
+                registered_proof,
+                cache_path,
+                sealed_sector,
+                ChannelWriter(sender),
+                prover_id,
+                sector_id,
+                comm_d,
+                ticket,
+                offset,
+                num_bytes,
+            )
+        })?;
+
+    Ok(UnsealedRangeReader {
+        receiver,
+        handle: Some(handle),
+        cancelled,
+        chunk: Vec::new(),
+        chunk_offset: 0,
+    })
+}
+
+/// Verifies unsealed, un-bit-padded sector bytes, e.g. the output of [`get_unsealed_range`]
+/// or [`unseal_range`], against the data commitment of the sector.
+///
+/// The pieces of a sector are laid out as by [`add_piece`]: each piece starts at a multiple of
+/// its padded size, and the bytes in between are zero. The range must not split a piece. The
+/// `piece_infos` are first checked to commit to `comm_d`, then the commitment of every piece
+/// within the range is recomputed from the bytes read from `unsealed` and compared against its
+/// piece info, and the bytes between the pieces are checked to be zero.
+///
+/// # Arguments
+///
+/// * `registered_proof` - Selected seal operation.
+/// * `unsealed` - A byte source of the unsealed bytes of the range.
+/// * `comm_d` - The commitment to the sector's data.
+/// * `piece_infos` - The piece info (commitment and byte length) for each piece in the sector.
+/// * `offset` - The byte index in the unsealed sector of the first byte of the range.
+/// * `num_bytes` - The number of bytes in the range.
+///
+/// If the bytes of a piece do not match its commitment, the returned error contains an
+/// [`UnsealedDataMismatch`] with the nodes of the first such piece.
+pub fn verify_unsealed_range<R: Read>(
+    registered_proof: RegisteredSealProof,
+    unsealed: R,
+    comm_d: Commitment,
+    piece_infos: &[PieceInfo],
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+) -> Result<()> {
+    ensure!(
+        registered_proof.major_version() == 1,
+        "unusupported version"
+    );
+
+    verify_unsealed_pieces(
+        registered_proof.sector_size(),
+        unsealed,
+        comm_d,
+        piece_infos,
+        offset,
+        num_bytes,
+    )
+}
+
+/// Recomputes the commitments of the pieces within a range of unsealed bytes, see
+/// [`verify_unsealed_range`].
+pub(crate) fn verify_unsealed_pieces<R: Read>(
+    sector_size: SectorSize,
+    mut unsealed: R,
+    comm_d: Commitment,
+    piece_infos: &[PieceInfo],
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+) -> Result<()> {
+    let expected = filecoin_proofs_v1::compute_comm_d(sector_size, piece_infos)?;
+    ensure!(expected == comm_d, "piece infos do not match comm_d");
+
+    let end = offset.0 + num_bytes.0;
+    let mut position = offset.0;
+    let mut pieces_end = 0;
+    let mut piece_sizes = Vec::with_capacity(piece_infos.len());
+    for piece_info in piece_infos {
+        let piece_start = u64::from(get_piece_start_byte(&piece_sizes, piece_info.size));
+        let piece_end = piece_start + piece_info.size.0;
+        piece_sizes.push(piece_info.size);
+        pieces_end = piece_end;
+        if piece_end <= offset.0 {
+            continue;
+        }
+        if piece_start >= end {
+            break;
+        }
+        ensure!(
+            piece_start >= offset.0 && piece_end <= end,
+            "range {}..{} does not align with piece {}..{}",
+            offset.0,
+            end,
+            piece_start,
+            piece_end
+        );
+
+        // Pieces are aligned to their padded size, the bytes in between are zero.
+        verify_zero_bytes(&mut unsealed, position, piece_start)?;
+        let piece = filecoin_proofs_v1::generate_piece_commitment(
+            (&mut unsealed).take(piece_info.size.0),
+            piece_info.size,
+        )?;
+        if piece.commitment != piece_info.commitment {
+            return Err(unsealed_data_mismatch(piece_start, piece_end).into());
+        }
+        position = piece_end;
+    }
+    ensure!(
+        end <= pieces_end,
+        "range {}..{} exceeds the pieces of the sector",
+        offset.0,
+        end
+    );
+    verify_zero_bytes(&mut unsealed, position, end)?;
+
+    Ok(())
+}
+
+/// Reads the unsealed bytes `start..end`, which lie between two pieces, and checks that they
+/// are zero.
+fn verify_zero_bytes<R: Read>(unsealed: R, start: u64, end: u64) -> Result<()> {
+    let mut padding = Vec::with_capacity((end - start) as usize);
+    unsealed.take(end - start).read_to_end(&mut padding)?;
+    ensure!(
+        padding.len() as u64 == end - start,
+        "unsealed range ended before byte {}",
+        end
+    );
+    if padding.iter().any(|&byte| byte != 0) {
+        return Err(unsealed_data_mismatch(start, end).into());
+    }
+
+    Ok(())
+}
+
+/// The nodes holding the unsealed bytes `start..end`.
+fn unsealed_data_mismatch(start: u64, end: u64) -> UnsealedDataMismatch {
+    let node_size = NODE_SIZE as u64;
+    let padded_start = PaddedBytesAmount::from(UnpaddedBytesAmount(start));
+    let padded_end = PaddedBytesAmount::from(UnpaddedBytesAmount(end));
+    UnsealedDataMismatch {
+        first_node: padded_start.0 / node_size,
+        num_nodes: (padded_end.0 - padded_start.0) / node_size,
+    }
+}
+
 /// Generates a piece commitment for the provided byte source. Returns an error
 /// if the byte source produced more than `piece_size` bytes.
 ///
@@ -1937,3 +2587,311 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn piece_data(len: usize, seed: u8) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+            .collect()
+    }
+
+    /// Returns a reader fed by `produce` on a background thread, as `unseal_range_reader`
+    /// feeds it with `unseal_range`.
+    fn channel_reader<F>(produce: F) -> UnsealedRangeReader
+    where
+        F: FnOnce(ChannelWriter) -> Result<UnpaddedBytesAmount> + Send + 'static,
+    {
+        let (sender, receiver) = sync_channel(UNSEALED_READER_CHANNEL_BOUND);
+        let handle = thread::spawn(move || produce(ChannelWriter(sender)));
+
+        UnsealedRangeReader {
+            receiver,
+            handle: Some(handle),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            chunk: Vec::new(),
+            chunk_offset: 0,
+        }
+    }
+
+    #[test]
+    fn test_unsealed_range_reader() {
+        let data = piece_data(100_000, 3);
+        let written = data.clone();
+        let mut reader = channel_reader(move |mut writer| {
+            for chunk in written.chunks(777) {
+                writer.write_all(chunk)?;
+            }
+            Ok(UnpaddedBytesAmount(written.len() as u64))
+        });
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).expect("failed to read");
+        assert_eq!(read, data);
+
+        // Errors of the unsealing thread are returned by the reader.
+        let mut reader = channel_reader(|mut writer| {
+            writer.write_all(&[1, 2, 3])?;
+            Err(anyhow!("unsealing failed"))
+        });
+        let mut read = Vec::new();
+        let err = reader
+            .read_to_end(&mut read)
+            .expect_err("error not returned");
+        assert!(err.to_string().contains("unsealing failed"));
+        assert_eq!(read, vec![1, 2, 3]);
+
+        // Once the reader is dropped, the writer and the sealed sector reader stop.
+        let (sender, receiver) = sync_channel(UNSEALED_READER_CHANNEL_BOUND);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let reader = UnsealedRangeReader {
+            receiver,
+            handle: None,
+            cancelled: cancelled.clone(),
+            chunk: Vec::new(),
+            chunk_offset: 0,
+        };
+        let mut sealed = CancellableReader {
+            inner: &[0u8; 8][..],
+            cancelled,
+        };
+        drop(reader);
+        assert!(ChannelWriter(sender).write_all(&[1]).is_err());
+        assert!(sealed.read(&mut [0; 8]).is_err());
+
+        // `unseal_range` reads the sealed sector with `read_to_end`, which must give up.
+        let mut sector = Vec::new();
+        let err = sealed
+            .read_to_end(&mut sector)
+            .expect_err("cancelled read succeeded");
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(sector.is_empty());
+    }
+
+    #[test]
+    fn test_verify_unsealed_range() {
+        let registered_proof = RegisteredSealProof::StackedDrg2KiBV1_1;
+        let size = UnpaddedBytesAmount(1016);
+
+        let mut data = piece_data(1016, 1);
+        data.extend(piece_data(1016, 2));
+        let piece_infos: Vec<PieceInfo> = data
+            .chunks(1016)
+            .map(|piece| {
+                generate_piece_commitment(registered_proof, piece, size)
+                    .expect("failed to generate piece commitment")
+            })
+            .collect();
+        let comm_d =
+            compute_comm_d(registered_proof, &piece_infos).expect("failed to compute comm_d");
+
+        verify_unsealed_range(
+            registered_proof,
+            &data[..],
+            comm_d,
+            &piece_infos,
+            UnpaddedByteIndex(0),
+            UnpaddedBytesAmount(2032),
+        )
+        .expect("failed to verify sector");
+        verify_unsealed_range(
+            registered_proof,
+            &data[1016..],
+            comm_d,
+            &piece_infos,
+            UnpaddedByteIndex(1016),
+            size,
+        )
+        .expect("failed to verify second piece");
+
+        // Ranges must cover whole pieces.
+        assert!(verify_unsealed_range(
+            registered_proof,
+            &data[1..],
+            comm_d,
+            &piece_infos,
+            UnpaddedByteIndex(1),
+            UnpaddedBytesAmount(2031),
+        )
+        .is_err());
+
+        data[1500] ^= 0x01;
+        let err = verify_unsealed_range(
+            registered_proof,
+            &data[..],
+            comm_d,
+            &piece_infos,
+            UnpaddedByteIndex(0),
+            UnpaddedBytesAmount(2032),
+        )
+        .expect_err("corrupted data verified");
+        let mismatch = err
+            .downcast_ref::<UnsealedDataMismatch>()
+            .expect("expected a data mismatch");
+        assert_eq!(
+            *mismatch,
+            UnsealedDataMismatch {
+                first_node: 32,
+                num_nodes: 32,
+            }
+        );
+    }
+
+    #[test]
+    fn test_verify_unsealed_range_aligned_pieces() {
+        let registered_proof = RegisteredSealProof::StackedDrg2KiBV1_1;
+
+        // The second piece is aligned to its padded size of 1024 bytes, i.e. it starts at
+        // unpadded byte 1016 instead of right after the first piece.
+        let small = piece_data(127, 1);
+        let large = piece_data(1016, 2);
+        let piece_infos = vec![
+            generate_piece_commitment(registered_proof, &small[..], UnpaddedBytesAmount(127))
+                .expect("failed to generate piece commitment"),
+            generate_piece_commitment(registered_proof, &large[..], UnpaddedBytesAmount(1016))
+                .expect("failed to generate piece commitment"),
+        ];
+        let comm_d =
+            compute_comm_d(registered_proof, &piece_infos).expect("failed to compute comm_d");
+
+        let mut data = small;
+        data.resize(1016, 0);
+        data.extend(large);
+
+        verify_unsealed_range(
+            registered_proof,
+            &data[..],
+            comm_d,
+            &piece_infos,
+            UnpaddedByteIndex(0),
+            UnpaddedBytesAmount(2032),
+        )
+        .expect("failed to verify sector");
+        verify_unsealed_range(
+            registered_proof,
+            &data[..127],
+            comm_d,
+            &piece_infos,
+            UnpaddedByteIndex(0),
+            UnpaddedBytesAmount(127),
+        )
+        .expect("failed to verify first piece");
+        verify_unsealed_range(
+            registered_proof,
+            &data[1016..],
+            comm_d,
+            &piece_infos,
+            UnpaddedByteIndex(1016),
+            UnpaddedBytesAmount(1016),
+        )
+        .expect("failed to verify second piece");
+
+        // The alignment bytes must be zero.
+        data[500] = 1;
+        let err = verify_unsealed_range(
+            registered_proof,
+            &data[..],
+            comm_d,
+            &piece_infos,
+            UnpaddedByteIndex(0),
+            UnpaddedBytesAmount(2032),
+        )
+        .expect_err("non-zero alignment bytes verified");
+        assert_eq!(
+            err.downcast_ref::<UnsealedDataMismatch>(),
+            Some(&UnsealedDataMismatch {
+                first_node: 4,
+                num_nodes: 28,
+            })
+        );
+    }
+
+    #[test]
+    fn test_bisect_batch() {
+        let invalid = [3, 4, 9];
+        let mut results: Vec<Result<bool>> = (0..12).map(|_| Ok(true)).collect();
+        results[7] = Err(anyhow!("invalid proof length"));
+
+        let indices: Vec<usize> = (0..12).filter(|&i| i != 7).collect();
+        let verify = |indices: &[usize]| -> Result<bool> {
+            Ok(!indices.iter().any(|i| invalid.contains(i)))
+        };
+        bisect_batch(&indices, &verify, &mut results).expect("failed to bisect batch");
+
+        for (i, result) in results.iter().enumerate() {
+            match i {
+                3 | 4 | 9 => assert!(!result.as_ref().expect("unexpected error")),
+                7 => assert!(result.is_err()),
+                _ => assert!(result.as_ref().expect("unexpected error")),
+            }
+        }
+
+        // Verification errors are returned without bisecting.
+        let calls = std::cell::Cell::new(0);
+        let failing = |_: &[usize]| -> Result<bool> {
+            calls.set(calls.get() + 1);
+            bail!("missing parameters")
+        };
+        assert!(bisect_batch(&indices, &failing, &mut results).is_err());
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_plan_seal_commit_aggregation() {
+        let plan = |registered_proof, num_sectors| {
+            plan_seal_commit_aggregation(
+                registered_proof,
+                RegisteredAggregationProof::SnarkPackV2,
+                num_sectors,
+            )
+            .expect("failed to plan aggregation")
+        };
+
+        // One partition: 5 sectors are padded to 8, as aggregates contain at least 4 sectors.
+        assert_eq!(plan(RegisteredSealProof::StackedDrg2KiBV1_1, 5), vec![0..5]);
+        assert_eq!(plan(RegisteredSealProof::StackedDrg2KiBV1_1, 8), vec![0..8]);
+        // 12 = 8 + 4 sectors need no padding.
+        assert_eq!(
+            plan(RegisteredSealProof::StackedDrg2KiBV1_1, 12),
+            vec![0..8, 8..12]
+        );
+        assert!(plan_seal_commit_aggregation(
+            RegisteredSealProof::StackedDrg2KiBV1_1,
+            RegisteredAggregationProof::SnarkPackV2,
+            3,
+        )
+        .is_err());
+
+        // Ten partitions: 40 proofs padded to 64.
+        let registered_proof = RegisteredSealProof::StackedDrg32GiBV1_1;
+        assert_eq!(plan(registered_proof, 4), vec![0..4]);
+        assert_eq!(plan(registered_proof, 819), vec![0..819]);
+        assert_eq!(
+            RegisteredAggregationProof::SnarkPackV2.sector_limits(registered_proof),
+            (4, 819)
+        );
+        assert_eq!(
+            RegisteredAggregationProof::SnarkPackV2
+                .sector_limits(RegisteredSealProof::StackedDrg2KiBV1_1),
+            (4, 819)
+        );
+
+        // NI-PoRep sectors are aggregated on their own terms.
+        assert_eq!(
+            RegisteredAggregationProof::SnarkPackV2
+                .sector_limits(RegisteredSealProof::StackedDrg2KiBV1_2_Feat_NonInteractivePoRep),
+            (1, 65)
+        );
+
+        let (_, max_sectors) =
+            RegisteredAggregationProof::SnarkPackV2.sector_limits(registered_proof);
+        let ranges = plan(registered_proof, 2000);
+        assert_eq!(ranges.first().map(|range| range.start), Some(0));
+        assert_eq!(ranges.last().map(|range| range.end), Some(2000));
+        for (range, next) in ranges.iter().zip(ranges.iter().skip(1)) {
+            assert_eq!(range.end, next.start);
+        }
+        assert!(ranges.iter().all(|range| range.len() <= max_sectors));
+    }
+}
@@ -1,17 +1,22 @@
 //! Data types for sector replication.
-use std::path::PathBuf;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
 
 use crate::{Commitment, RegisteredPoStProof};
 
-// A byte serialized representation of a vanilla proof.
+// A byte serialized representation of a vanilla proof, see `VanillaProofEnvelope`.
 pub type VanillaProofBytes = Vec<u8>;
 
-// A byte serialized representation of a vanilla partition proof.
+// A byte serialized representation of a vanilla partition proof, see `VanillaProofEnvelope`.
 #[repr(transparent)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PartitionProofBytes(pub Vec<u8>);
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct PrivateReplicaInfo {
     /// The version of this replica.
     pub(crate) registered_proof: RegisteredPoStProof,
@@ -37,9 +42,57 @@ impl PrivateReplicaInfo {
             replica_path,
         }
     }
+
+    /// Like [`PrivateReplicaInfo::new`], but checks that `cache_dir` is a directory and that
+    /// `replica_path` is a file of the sector size of `registered_proof`.
+    pub fn try_new(
+        registered_proof: RegisteredPoStProof,
+        comm_r: Commitment,
+        cache_dir: PathBuf,
+        replica_path: PathBuf,
+    ) -> Result<Self> {
+        ensure!(
+            cache_dir.is_dir(),
+            "cache dir {:?} is not a directory",
+            cache_dir
+        );
+        let replica_len = fs::metadata(&replica_path)
+            .with_context(|| format!("could not read replica {:?}", replica_path))?
+            .len();
+        let sector_size = u64::from(registered_proof.sector_size());
+        ensure!(
+            replica_len == sector_size,
+            "replica {:?} is {} bytes, expected {}",
+            replica_path,
+            replica_len,
+            sector_size
+        );
+
+        Ok(Self::new(registered_proof, comm_r, cache_dir, replica_path))
+    }
+
+    /// The version of this replica.
+    pub fn registered_proof(&self) -> RegisteredPoStProof {
+        self.registered_proof
+    }
+
+    /// The replica commitment.
+    pub fn comm_r(&self) -> Commitment {
+        self.comm_r
+    }
+
+    /// The directory containing the sector-specific assets.
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// The path of the replica.
+    pub fn replica_path(&self) -> &Path {
+        &self.replica_path
+    }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct PublicReplicaInfo {
     /// The version of this replica.
     pub(crate) registered_proof: RegisteredPoStProof,
@@ -54,4 +107,93 @@ impl PublicReplicaInfo {
             comm_r,
         }
     }
+
+    /// The version of this replica.
+    pub fn registered_proof(&self) -> RegisteredPoStProof {
+        self.registered_proof
+    }
+
+    /// The replica commitment.
+    pub fn comm_r(&self) -> Commitment {
+        self.comm_r
+    }
+}
+
+/// The first range of nodes whose unsealed or decoded data does not match its commitment.
+///
+/// Verification functions return it wrapped in an [`anyhow::Error`], from which it can be
+/// retrieved with [`anyhow::Error::downcast_ref`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnsealedDataMismatch {
+    /// Index of the first node of the range within the sector.
+    pub first_node: u64,
+    /// Number of nodes in the range.
+    pub num_nodes: u64,
+}
+
+impl fmt::Display for UnsealedDataMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "data of nodes {}..{} does not match its commitment",
+            self.first_node,
+            self.first_node + self.num_nodes
+        )
+    }
+}
+
+impl std::error::Error for UnsealedDataMismatch {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_private_replica_info_try_new() {
+        let dir = std::env::temp_dir().join(format!("replica-info-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("failed to create test dir");
+        let replica_path = dir.join("sealed");
+        let registered_proof = RegisteredPoStProof::StackedDrgWindow2KiBV1_2;
+        fs::write(&replica_path, vec![0; 2048]).expect("failed to write replica");
+
+        let info = PrivateReplicaInfo::try_new(
+            registered_proof,
+            [1; 32],
+            dir.clone(),
+            replica_path.clone(),
+        )
+        .expect("valid replica rejected");
+        assert_eq!(info.registered_proof(), registered_proof);
+        assert_eq!(info.comm_r(), [1; 32]);
+        assert_eq!(info.cache_dir(), dir.as_path());
+        assert_eq!(info.replica_path(), replica_path.as_path());
+
+        let json = serde_json::to_string(&info).expect("failed to serialize");
+        let decoded: PrivateReplicaInfo = serde_json::from_str(&json).expect("failed to parse");
+        assert_eq!(decoded, info);
+
+        assert!(PrivateReplicaInfo::try_new(
+            registered_proof,
+            [1; 32],
+            replica_path.clone(),
+            replica_path.clone()
+        )
+        .is_err());
+        assert!(PrivateReplicaInfo::try_new(
+            RegisteredPoStProof::StackedDrgWindow8MiBV1_2,
+            [1; 32],
+            dir.clone(),
+            replica_path
+        )
+        .is_err());
+        assert!(PrivateReplicaInfo::try_new(
+            registered_proof,
+            [1; 32],
+            dir.clone(),
+            dir.join("missing")
+        )
+        .is_err());
+
+        fs::remove_dir_all(&dir).expect("failed to remove test dir");
+    }
 }
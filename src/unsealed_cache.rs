@@ -0,0 +1,623 @@
+//! A cache of fully unsealed sectors on disk.
+//!
+//! Unsealing cannot be limited to a range of nodes: the labels of every layer depend on
+//! parents spread across the whole previous layer, so recovering a single node requires
+//! regenerating all labels of the sector. Retrieval of many small ranges from the same sector
+//! therefore shares one unsealing through this cache, which keeps the unpadded sector data of
+//! recently read sectors within a byte budget.
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+
+use anyhow::{ensure, Context, Result};
+
+use crate::seal::get_unsealed_range;
+use crate::{
+    Commitment, PaddedBytesAmount, ProverId, RegisteredSealProof, SectorId, Ticket,
+    UnpaddedByteIndex, UnpaddedBytesAmount,
+};
+
+/// File extension of the unsealed sectors stored in the cache directory.
+const UNSEALED_EXTENSION: &str = "unsealed";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct CacheKey {
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+}
+
+impl CacheKey {
+    fn file_name(&self) -> String {
+        format!(
+            "{}-{}-{}.{}",
+            hex(&self.prover_id),
+            u64::from(self.sector_id),
+            hex(&self.comm_d),
+            UNSEALED_EXTENSION
+        )
+    }
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    path: PathBuf,
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// Sectors which are being unsealed into the cache.
+    unsealing: HashSet<CacheKey>,
+    used_bytes: u64,
+    clock: u64,
+}
+
+impl CacheState {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Removes least recently used entries until `needed` more bytes fit into `budget`.
+    fn evict(&mut self, needed: u64, budget: u64) -> Result<()> {
+        while self.used_bytes + needed > budget {
+            let key = match self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key)
+            {
+                Some(key) => key,
+                None => break,
+            };
+            self.remove(&key)?;
+        }
+
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &CacheKey) -> Result<()> {
+        if let Some(entry) = self.entries.remove(key) {
+            self.used_bytes -= entry.size;
+            // Readers which already opened the file keep access to its data.
+            match fs::remove_file(&entry.path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    return Err(err).with_context(|| {
+                        format!("failed to remove cached sector {:?}", entry.path)
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A directory of unsealed sectors, bounded by a byte budget and evicted in least recently
+/// used order.
+///
+/// The first read of a sector unseals it entirely and stores its unpadded data in the cache
+/// directory. Subsequent reads of the same sector are served from that copy. Sectors are
+/// identified by prover ID, sector ID and `comm_d`. Concurrent first reads of the same sector
+/// unseal it once: later readers wait for the first one to finish.
+#[derive(Debug)]
+pub struct UnsealedSectorCache {
+    dir: PathBuf,
+    budget: u64,
+    state: Mutex<CacheState>,
+    /// Signalled whenever a sector is no longer being unsealed.
+    unsealed: Condvar,
+}
+
+impl UnsealedSectorCache {
+    /// Creates a cache storing at most `budget` bytes of unsealed data in `dir`.
+    ///
+    /// The directory is owned by the cache: it is created if missing and unsealed sectors left
+    /// over from a previous cache are removed.
+    pub fn new<P: AsRef<Path>>(dir: P, budget: u64) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create cache directory {:?}", dir))?;
+
+        let extension = format!(".{}", UNSEALED_EXTENSION);
+        let tmp_extension = format!(".{}.tmp", UNSEALED_EXTENSION);
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("");
+            if name.ends_with(&extension) || name.ends_with(&tmp_extension) {
+                fs::remove_file(&path)
+                    .with_context(|| format!("failed to remove stale cache file {:?}", path))?;
+            }
+        }
+
+        Ok(UnsealedSectorCache {
+            dir,
+            budget,
+            state: Mutex::new(CacheState::default()),
+            unsealed: Condvar::new(),
+        })
+    }
+
+    /// Returns the byte budget of the cache.
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    /// Returns the number of bytes currently used by cached sectors, including the space
+    /// reserved for sectors being unsealed.
+    pub fn used_bytes(&self) -> u64 {
+        self.state.lock().expect("cache state poisoned").used_bytes
+    }
+
+    /// Opens the unpadded data of a sector, unsealing it into the cache first if it is not
+    /// cached yet. Offsets within the returned file are unpadded byte offsets into the sector.
+    ///
+    /// # Arguments
+    ///
+    /// * `registered_proof` - Selected seal operation.
+    /// * `cache_path` - Path to the directory in which the sector data's Merkle tree is written.
+    /// * `sealed_path` - Path to the sealed sector file.
+    /// * `prover_id` - Unique ID of the storage provider.
+    /// * `sector_id` - ID of the sector, usually relative to the miner.
+    /// * `comm_d` - The commitment to the sector's data.
+    /// * `ticket` - The ticket that was used to generate the sector's replica-id.
+    ///
+    /// Returns the opened file of the cached, unsealed sector.
+    pub fn open<T: Into<PathBuf> + AsRef<Path>>(
+        &self,
+        registered_proof: RegisteredSealProof,
+        cache_path: T,
+        sealed_path: T,
+        prover_id: ProverId,
+        sector_id: SectorId,
+        comm_d: Commitment,
+        ticket: Ticket,
+    ) -> Result<File> {
+        let key = CacheKey {
+            prover_id,
+            sector_id,
+            comm_d,
+        };
+        let size = u64::from(UnpaddedBytesAmount::from(PaddedBytesAmount::from(
+            registered_proof.sector_size(),
+        )));
+
+        self.open_with(key, size, |tmp_path| {
+            get_unsealed_range(
+                registered_proof,
+                cache_path.into(),
+                sealed_path.into(),
+                tmp_path.to_path_buf(),
+                prover_id,
+                sector_id,
+                comm_d,
+                ticket,
+                UnpaddedByteIndex(0),
+                UnpaddedBytesAmount(size),
+            )
+            .map(|_| ())
+        })
+    }
+
+    /// Opens the cached sector of `key`, calling `unseal` to write its `size` bytes to the
+    /// given path first if it is not cached yet. Only one caller unseals a sector at a time,
+    /// and only once its size fits into the budget.
+    fn open_with<F>(&self, key: CacheKey, size: u64, unseal: F) -> Result<File>
+    where
+        F: FnOnce(&Path) -> Result<()>,
+    {
+        ensure!(
+            size <= self.budget,
+            "unsealed sector size {} exceeds cache budget {}",
+            size,
+            self.budget
+        );
+
+        {
+            let mut state = self.state.lock().expect("cache state poisoned");
+            loop {
+                if let Some(file) = Self::open_cached(&mut state, &key)? {
+                    return Ok(file);
+                }
+                if !state.unsealing.contains(&key) {
+                    // Reserve the space of the sector before unsealing it. Space reserved by
+                    // other unseals is only released once they finish.
+                    state.evict(size, self.budget)?;
+                    if state.used_bytes + size <= self.budget {
+                        break;
+                    }
+                }
+                state = self.unsealed.wait(state).expect("cache state poisoned");
+            }
+            state.unsealing.insert(key);
+            state.used_bytes += size;
+        }
+        // Lets waiting callers retry and releases the reserved space, also if unsealing fails
+        // or panics.
+        let mut unsealing = UnsealingGuard {
+            cache: self,
+            key,
+            reserved: size,
+        };
+
+        // Unseal into a temporary file, so that a partially written sector is never served.
+        let path = self.dir.join(key.file_name());
+        let tmp_path = path.with_extension(format!("{}.tmp", UNSEALED_EXTENSION));
+        let unsealed = unseal(&tmp_path).and_then(|_| {
+            fs::rename(&tmp_path, &path)
+                .with_context(|| format!("failed to move unsealed sector to {:?}", path))
+        });
+        if let Err(err) = unsealed {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) => {
+                let _ = fs::remove_file(&path);
+                return Err(err)
+                    .with_context(|| format!("failed to open cached sector {:?}", path));
+            }
+        };
+
+        let mut state = self.state.lock().expect("cache state poisoned");
+        let last_used = state.tick();
+        state.entries.insert(
+            key,
+            CacheEntry {
+                path,
+                size,
+                last_used,
+            },
+        );
+        // The reserved space is now used by the entry.
+        unsealing.reserved = 0;
+
+        Ok(file)
+    }
+
+    /// Writes the bytes of a piece whose first (unpadded) byte begins at `offset` and ends at
+    /// `offset` plus `num_bytes`, exclusive, to `unsealed_output`. The sector is unsealed into
+    /// the cache first if it is not cached yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `registered_proof` - Selected seal operation.
+    /// * `cache_path` - Path to the directory in which the sector data's Merkle tree is written.
+    /// * `sealed_path` - Path to the sealed sector file.
+    /// * `unsealed_output` - A byte sink to which we write unsealed, un-bit-padded sector bytes.
+    /// * `prover_id` - Unique ID of the storage provider.
+    /// * `sector_id` - ID of the sector, usually relative to the miner.
+    /// * `comm_d` - The commitment to the sector's data.
+    /// * `ticket` - The ticket that was used to generate the sector's replica-id.
+    /// * `offset` - The byte index in the unsealed sector of the first byte that we want to read.
+    /// * `num_bytes` - The number of bytes that we want to read.
+    ///
+    /// Returns count of bytes written.
+    pub fn unsealed_range<T: Into<PathBuf> + AsRef<Path>, W: Write>(
+        &self,
+        registered_proof: RegisteredSealProof,
+        cache_path: T,
+        sealed_path: T,
+        mut unsealed_output: W,
+        prover_id: ProverId,
+        sector_id: SectorId,
+        comm_d: Commitment,
+        ticket: Ticket,
+        offset: UnpaddedByteIndex,
+        num_bytes: UnpaddedBytesAmount,
+    ) -> Result<UnpaddedBytesAmount> {
+        let mut file = self.open(
+            registered_proof,
+            cache_path,
+            sealed_path,
+            prover_id,
+            sector_id,
+            comm_d,
+            ticket,
+        )?;
+
+        let size = file.metadata()?.len();
+        ensure!(
+            offset.0 + num_bytes.0 <= size,
+            "range {}..{} exceeds unsealed sector size {}",
+            offset.0,
+            offset.0 + num_bytes.0,
+            size
+        );
+
+        file.seek(SeekFrom::Start(offset.0))?;
+        let written = io::copy(&mut file.take(num_bytes.0), &mut unsealed_output)?;
+
+        Ok(UnpaddedBytesAmount(written))
+    }
+
+    /// Removes a sector from the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `prover_id` - Unique ID of the storage provider.
+    /// * `sector_id` - ID of the sector, usually relative to the miner.
+    /// * `comm_d` - The commitment to the sector's data.
+    pub fn remove(
+        &self,
+        prover_id: ProverId,
+        sector_id: SectorId,
+        comm_d: Commitment,
+    ) -> Result<()> {
+        let key = CacheKey {
+            prover_id,
+            sector_id,
+            comm_d,
+        };
+
+        self.state
+            .lock()
+            .expect("cache state poisoned")
+            .remove(&key)
+    }
+
+    /// Removes all sectors from the cache.
+    pub fn clear(&self) -> Result<()> {
+        let mut state = self.state.lock().expect("cache state poisoned");
+        let keys: Vec<CacheKey> = state.entries.keys().copied().collect();
+        for key in keys {
+            state.remove(&key)?;
+        }
+
+        Ok(())
+    }
+
+    fn open_cached(state: &mut CacheState, key: &CacheKey) -> Result<Option<File>> {
+        let last_used = state.tick();
+
+        let path = match state.entries.get_mut(key) {
+            Some(entry) => {
+                entry.last_used = last_used;
+                entry.path.clone()
+            }
+            None => return Ok(None),
+        };
+
+        match File::open(&path) {
+            Ok(file) => Ok(Some(file)),
+            // The file was removed behind our back, unseal the sector again.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                state.remove(key)?;
+                Ok(None)
+            }
+            Err(err) => {
+                Err(err).with_context(|| format!("failed to open cached sector {:?}", path))
+            }
+        }
+    }
+}
+
+/// Marks a sector as no longer being unsealed when dropped, releasing the `reserved` bytes.
+struct UnsealingGuard<'a> {
+    cache: &'a UnsealedSectorCache,
+    key: CacheKey,
+    reserved: u64,
+}
+
+impl Drop for UnsealingGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.cache.state.lock() {
+            state.unsealing.remove(&self.key);
+            state.used_bytes -= self.reserved;
+        }
+        self.cache.unsealed.notify_all();
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut output, b| {
+        let _ = write!(output, "{:02x}", b);
+        output
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn key(sector_id: u64) -> CacheKey {
+        CacheKey {
+            prover_id: [1; 32],
+            sector_id: SectorId::from(sector_id),
+            comm_d: [2; 32],
+        }
+    }
+
+    fn test_cache(name: &str, budget: u64) -> (PathBuf, UnsealedSectorCache) {
+        let dir =
+            std::env::temp_dir().join(format!("unsealed-cache-{}-{}", name, std::process::id()));
+        let cache = UnsealedSectorCache::new(&dir, budget).expect("failed to create cache");
+        (dir, cache)
+    }
+
+    fn open(cache: &UnsealedSectorCache, key: CacheKey, unseals: &AtomicUsize) -> Vec<u8> {
+        let mut file = cache
+            .open_with(key, 4, |path| {
+                unseals.fetch_add(1, Ordering::SeqCst);
+                let byte = u64::from(key.sector_id) as u8;
+                fs::write(path, [byte; 4])?;
+                Ok(())
+            })
+            .expect("failed to open sector");
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).expect("failed to read sector");
+        data
+    }
+
+    #[test]
+    fn test_cache_hit_and_miss() {
+        let (dir, cache) = test_cache("hit", 8);
+        let unseals = AtomicUsize::new(0);
+
+        assert_eq!(open(&cache, key(1), &unseals), [1; 4]);
+        assert_eq!(unseals.load(Ordering::SeqCst), 1);
+        assert_eq!(open(&cache, key(1), &unseals), [1; 4]);
+        assert_eq!(unseals.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.used_bytes(), 4);
+
+        cache
+            .remove(key(1).prover_id, key(1).sector_id, key(1).comm_d)
+            .expect("failed to remove sector");
+        assert_eq!(cache.used_bytes(), 0);
+        assert_eq!(open(&cache, key(1), &unseals), [1; 4]);
+        assert_eq!(unseals.load(Ordering::SeqCst), 2);
+
+        // A failed unseal leaves nothing behind.
+        assert!(cache
+            .open_with(key(2), 4, |_| Err(anyhow::anyhow!("unseal failed")))
+            .is_err());
+        assert_eq!(cache.used_bytes(), 4);
+        assert_eq!(open(&cache, key(2), &unseals), [2; 4]);
+        assert_eq!(unseals.load(Ordering::SeqCst), 3);
+
+        fs::remove_dir_all(&dir).expect("failed to remove test dir");
+    }
+
+    #[test]
+    fn test_cache_eviction() {
+        let (dir, cache) = test_cache("eviction", 8);
+        let unseals = AtomicUsize::new(0);
+
+        open(&cache, key(1), &unseals);
+        open(&cache, key(2), &unseals);
+        // Sector 1 is now used more recently than sector 2, which is evicted for sector 3.
+        open(&cache, key(1), &unseals);
+        open(&cache, key(3), &unseals);
+        assert_eq!(cache.used_bytes(), 8);
+        assert_eq!(unseals.load(Ordering::SeqCst), 3);
+        assert!(!dir.join(key(2).file_name()).exists());
+
+        open(&cache, key(1), &unseals);
+        open(&cache, key(3), &unseals);
+        assert_eq!(unseals.load(Ordering::SeqCst), 3);
+        open(&cache, key(2), &unseals);
+        assert_eq!(unseals.load(Ordering::SeqCst), 4);
+        assert!(!dir.join(key(1).file_name()).exists());
+
+        // Sectors larger than the budget are never unsealed.
+        assert!(cache
+            .open_with(key(4), 9, |_| panic!(
+                "sector exceeding the budget unsealed"
+            ))
+            .is_err());
+        assert_eq!(cache.used_bytes(), 8);
+
+        cache.clear().expect("failed to clear cache");
+        assert_eq!(cache.used_bytes(), 0);
+        assert_eq!(fs::read_dir(&dir).expect("failed to read dir").count(), 0);
+
+        fs::remove_dir_all(&dir).expect("failed to remove test dir");
+    }
+
+    #[test]
+    fn test_cache_concurrent_unseal() {
+        let (dir, cache) = test_cache("concurrent", 8);
+        let cache = Arc::new(cache);
+        let unseals = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = Arc::clone(&cache);
+                let unseals = Arc::clone(&unseals);
+                thread::spawn(move || {
+                    let mut file = cache
+                        .open_with(key(1), 4, |path| {
+                            unseals.fetch_add(1, Ordering::SeqCst);
+                            let mut file = File::create(path)?;
+                            for _ in 0..4 {
+                                thread::sleep(Duration::from_millis(10));
+                                file.write_all(&[1])?;
+                            }
+                            Ok(())
+                        })
+                        .expect("failed to open sector");
+                    let mut data = Vec::new();
+                    file.read_to_end(&mut data).expect("failed to read sector");
+                    data
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            assert_eq!(handle.join().expect("reader panicked"), [1; 4]);
+        }
+        assert_eq!(unseals.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.used_bytes(), 4);
+
+        fs::remove_dir_all(&dir).expect("failed to remove test dir");
+    }
+
+    #[test]
+    fn test_cache_budget_reservation() {
+        let (dir, cache) = test_cache("reservation", 4);
+        let cache = Arc::new(cache);
+        let active = Arc::new(AtomicUsize::new(0));
+
+        // Two sectors which do not fit into the budget together are never unsealed at once.
+        let handles: Vec<_> = (1..=2)
+            .map(|sector_id| {
+                let cache = Arc::clone(&cache);
+                let active = Arc::clone(&active);
+                thread::spawn(move || {
+                    cache
+                        .open_with(key(sector_id), 4, |path| {
+                            assert_eq!(active.fetch_add(1, Ordering::SeqCst), 0);
+                            thread::sleep(Duration::from_millis(20));
+                            fs::write(path, [sector_id as u8; 4])?;
+                            active.fetch_sub(1, Ordering::SeqCst);
+                            Ok(())
+                        })
+                        .expect("failed to open sector");
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().expect("reader panicked");
+        }
+        assert_eq!(cache.used_bytes(), 4);
+
+        // Failures release the reserved space and leave no files behind.
+        cache.clear().expect("failed to clear cache");
+        assert!(cache
+            .open_with(key(3), 4, |path| {
+                fs::write(path, [3; 2])?;
+                Err(anyhow::anyhow!("unseal failed"))
+            })
+            .is_err());
+        assert_eq!(cache.used_bytes(), 0);
+        fs::create_dir_all(dir.join(key(4).file_name()).join("blocked"))
+            .expect("failed to block cache file");
+        assert!(cache
+            .open_with(key(4), 4, |path| Ok(fs::write(path, [4; 4])?))
+            .is_err());
+        assert_eq!(cache.used_bytes(), 0);
+        let files: Vec<PathBuf> = fs::read_dir(&dir)
+            .expect("failed to read dir")
+            .map(|entry| entry.expect("failed to read entry").path())
+            .collect();
+        assert_eq!(files, vec![dir.join(key(4).file_name())]);
+
+        fs::remove_dir_all(&dir).expect("failed to remove test dir");
+    }
+}
@@ -1,16 +1,32 @@
 //! Update data within existing sealed sectors.
-use std::io::{Read, Write};
-use std::path::Path;
+use std::cmp;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
-use anyhow::{ensure, Result};
+use anyhow::{ensure, Context, Result};
+use bellperson::groth16;
+use filecoin_hashers::{Domain, Hasher};
+use rand::rngs::OsRng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::compound_proof::{self, CompoundProof};
+use storage_proofs_update::EmptySectorUpdateCompound;
 
+use filecoin_proofs_v1::pieces::get_piece_start_byte;
 use filecoin_proofs_v1::types::{
     EmptySectorUpdateEncoded, EmptySectorUpdateProof, MerkleTreeTrait, PartitionProof,
     SectorUpdateConfig,
 };
-use filecoin_proofs_v1::{with_shape, TreeRHasher};
+use filecoin_proofs_v1::{with_shape, DefaultPieceHasher, TreeRHasher, SINGLE_PARTITION_PROOF_LEN};
 
-use crate::{types::PartitionProofBytes, Commitment, PieceInfo, RegisteredUpdateProof};
+use crate::envelope::{update_challenge_digest, VanillaProofEnvelope};
+use crate::padding::UnpadReader;
+use crate::seal::verify_unsealed_pieces;
+use crate::{
+    types::PartitionProofBytes, Commitment, PaddedBytesAmount, PartitionSnarkProof, PieceInfo,
+    RegisteredUpdateProof, UnpaddedByteIndex, UnpaddedBytesAmount, NODE_SIZE,
+};
 
 fn empty_sector_update_encode_into_inner<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
     registered_proof: RegisteredUpdateProof,
@@ -151,6 +167,57 @@ where
     )
 }
 
+/// Verifies the output of [`empty_sector_update_decode_from`] against the data commitment of
+/// the updated replica.
+///
+/// The commitment of every piece is recomputed from the decoded data and compared against
+/// its piece info, after checking that the `piece_infos` commit to `comm_d_new`.
+///
+/// # Arguments
+/// * `registered_proof` - Selected sector update proof.
+/// * `out_data_path` - File path of the decoded data.
+/// * `comm_d_new` - Data commitment from updated replica.
+/// * `piece_infos` - The piece info (commitment and byte length) for each piece in the sector.
+///
+/// If the data of a piece does not match its commitment, the returned error contains an
+/// [`UnsealedDataMismatch`](crate::UnsealedDataMismatch) with the nodes of the first such
+/// piece.
+pub fn verify_empty_sector_update_decoded<R: AsRef<Path>>(
+    registered_proof: RegisteredUpdateProof,
+    out_data_path: R,
+    comm_d_new: Commitment,
+    piece_infos: &[PieceInfo],
+) -> Result<()> {
+    ensure!(
+        registered_proof.major_version() == 1,
+        "unusupported version"
+    );
+
+    let sector_size = registered_proof.sector_size();
+    let decoded = UnpadReader::new(
+        File::open(out_data_path.as_ref())?,
+        PaddedBytesAmount::from(sector_size),
+    );
+    // Pieces are aligned to their padded size, see `add_piece`.
+    let pieces_len = match piece_infos.split_last() {
+        Some((last, previous)) => {
+            let previous_sizes: Vec<UnpaddedBytesAmount> =
+                previous.iter().map(|piece_info| piece_info.size).collect();
+            u64::from(get_piece_start_byte(&previous_sizes, last.size)) + last.size.0
+        }
+        None => 0,
+    };
+
+    verify_unsealed_pieces(
+        sector_size,
+        decoded,
+        comm_d_new,
+        piece_infos,
+        UnpaddedByteIndex(0),
+        UnpaddedBytesAmount(pieces_len),
+    )
+}
+
 /// Reverses the encoding process for a certain range.
 ///
 /// This function is similar to [`emptu_sector_update_decode_from`], the difference is that it
@@ -203,6 +270,253 @@ where
     )
 }
 
+/// Number of padded bytes decoded at once by [`empty_sector_update_decode_unpadded_range`]
+/// and [`empty_sector_update_decode_from_parallel`], a multiple of the 128 bytes of a Fr32 block.
+const DECODE_RANGE_CHUNK_SIZE: u64 = 1 << 20;
+
+/// Reverses the encoding process for a range of the user data within an updated sector.
+///
+/// Unlike [`empty_sector_update_decode_from_range`], the range is given in unpadded bytes, as
+/// for [`unseal_range`](crate::seal::unseal_range). The nodes covering the range are read from
+/// the replica and sector key, decoded and unpadded, and exactly the requested bytes are
+/// written to `output_data`.
+///
+/// # Arguments
+/// * `registered_proof` - Selected sector update proof.
+/// * `comm_d` - Data commitment from the updated replica.
+/// * `comm_r` - Replica commitment of the empty sector.
+/// * `replica_path` - File path of the updated replica.
+/// * `sector_key_path` - Path to sector key originally used to seal sector.
+/// * `output_data` - Where the decoded data is written to.
+/// * `offset` - Offset of the first byte within the unpadded sector data.
+/// * `num_bytes` - Number of unpadded bytes to decode.
+///
+/// Returns the number of bytes written.
+pub fn empty_sector_update_decode_unpadded_range<R, S, W>(
+    registered_proof: RegisteredUpdateProof,
+    comm_d: Commitment,
+    comm_r: Commitment,
+    replica_path: R,
+    sector_key_path: S,
+    output_data: &mut W,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+) -> Result<UnpaddedBytesAmount>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    W: Write,
+{
+    ensure!(
+        registered_proof.major_version() == 1,
+        "unusupported version"
+    );
+
+    decode_unpadded_range(
+        registered_proof,
+        comm_d,
+        comm_r,
+        replica_path.as_ref(),
+        sector_key_path.as_ref(),
+        output_data,
+        offset,
+        num_bytes,
+        DECODE_RANGE_CHUNK_SIZE,
+    )
+}
+
+/// Decodes the unpadded bytes `offset..offset + num_bytes` of an updated replica, see
+/// [`empty_sector_update_decode_unpadded_range`], `chunk_size` padded bytes at a time.
+fn decode_unpadded_range<W: Write>(
+    registered_proof: RegisteredUpdateProof,
+    comm_d: Commitment,
+    comm_r: Commitment,
+    replica_path: &Path,
+    sector_key_path: &Path,
+    output_data: &mut W,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+    chunk_size: u64,
+) -> Result<UnpaddedBytesAmount> {
+    let sector_size = u64::from(registered_proof.sector_size());
+    let end = offset.0 + num_bytes.0;
+    ensure!(
+        end <= fr32::to_unpadded_bytes(sector_size),
+        "range {}..{} exceeds the unpadded sector size",
+        offset.0,
+        end
+    );
+    if num_bytes.0 == 0 {
+        return Ok(num_bytes);
+    }
+
+    // Decode whole Fr32 blocks, each of which consists of whole nodes.
+    let padded_start = offset.0 / 127 * 128;
+    let padded_end = (end + 126) / 127 * 128;
+
+    let mut replica = File::open(replica_path)
+        .with_context(|| format!("could not open replica {:?}", replica_path))?;
+    let mut sector_key = File::open(sector_key_path)
+        .with_context(|| format!("could not open sector key {:?}", sector_key_path))?;
+    replica.seek(SeekFrom::Start(padded_start))?;
+    sector_key.seek(SeekFrom::Start(padded_start))?;
+
+    let mut chunk_start = padded_start;
+    let mut skip = (offset.0 - padded_start / 128 * 127) as usize;
+    let mut remaining = num_bytes.0 as usize;
+    let mut decoded = Vec::new();
+    while chunk_start < padded_end {
+        let chunk_len = cmp::min(chunk_size, padded_end - chunk_start);
+
+        decoded.clear();
+        empty_sector_update_decode_from_range(
+            registered_proof,
+            comm_d,
+            comm_r,
+            &mut replica,
+            &mut sector_key,
+            &mut decoded,
+            (chunk_start / NODE_SIZE as u64) as usize,
+            (chunk_len / NODE_SIZE as u64) as usize,
+        )?;
+
+        let len = cmp::min(
+            remaining,
+            fr32::to_unpadded_bytes(chunk_len) as usize - skip,
+        );
+        fr32::write_unpadded(&decoded, output_data, skip, len)?;
+
+        remaining -= len;
+        skip = 0;
+        chunk_start += chunk_len;
+    }
+
+    Ok(num_bytes)
+}
+
+/// Reverses the encoding process like [`empty_sector_update_decode_from`], splitting the
+/// sector across `num_threads` threads.
+///
+/// Every thread decodes a contiguous range of nodes, reading the replica and sector key at
+/// its own offsets and writing its output at the same offset of `out_data_path`. The output
+/// is identical to that of [`empty_sector_update_decode_from`].
+///
+/// # Arguments
+/// * `registered_proof` - Selected sector update proof.
+/// * `out_data_path` - File path to output decoded data.
+/// * `replica_path` - File path of replica.
+/// * `sector_key_path` - Path to sector key originally used to seal sector.
+/// * `comm_d_new` - Data commitment from updated replica.
+/// * `comm_r_old` - Replica commitment of the empty sector.
+/// * `num_threads` - Number of threads to decode with.
+pub fn empty_sector_update_decode_from_parallel<R, S, T>(
+    registered_proof: RegisteredUpdateProof,
+    out_data_path: R,
+    replica_path: S,
+    sector_key_path: T,
+    comm_d_new: Commitment,
+    comm_r_old: Commitment,
+    num_threads: usize,
+) -> Result<()>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    ensure!(
+        registered_proof.major_version() == 1,
+        "unusupported version"
+    );
+    ensure!(num_threads > 0, "at least one thread is required");
+
+    let sector_size = u64::from(registered_proof.sector_size());
+    let out_data_path = out_data_path.as_ref();
+    let replica_path = replica_path.as_ref();
+    let sector_key_path = sector_key_path.as_ref();
+    File::create(out_data_path)
+        .and_then(|file| file.set_len(sector_size))
+        .with_context(|| format!("could not create {:?}", out_data_path))?;
+
+    // Split at Fr32 block boundaries, which are also node boundaries.
+    let range_len = (sector_size / num_threads as u64 + 127) / 128 * 128;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()?;
+
+    pool.install(|| {
+        (0..num_threads as u64)
+            .into_par_iter()
+            .map(|i| {
+                let start = cmp::min(i * range_len, sector_size);
+                let end = cmp::min(start + range_len, sector_size);
+
+                decode_padded_range(
+                    registered_proof,
+                    comm_d_new,
+                    comm_r_old,
+                    replica_path,
+                    sector_key_path,
+                    out_data_path,
+                    start,
+                    end,
+                )
+            })
+            .collect()
+    })
+}
+
+/// Decodes the padded bytes `start..end` of an updated replica into the same range of
+/// `out_data_path`.
+fn decode_padded_range(
+    registered_proof: RegisteredUpdateProof,
+    comm_d: Commitment,
+    comm_r: Commitment,
+    replica_path: &Path,
+    sector_key_path: &Path,
+    out_data_path: &Path,
+    start: u64,
+    end: u64,
+) -> Result<()> {
+    if start == end {
+        return Ok(());
+    }
+
+    let mut replica = File::open(replica_path)
+        .with_context(|| format!("could not open replica {:?}", replica_path))?;
+    let mut sector_key = File::open(sector_key_path)
+        .with_context(|| format!("could not open sector key {:?}", sector_key_path))?;
+    let mut output = OpenOptions::new()
+        .write(true)
+        .open(out_data_path)
+        .with_context(|| format!("could not open {:?}", out_data_path))?;
+    replica.seek(SeekFrom::Start(start))?;
+    sector_key.seek(SeekFrom::Start(start))?;
+    output.seek(SeekFrom::Start(start))?;
+
+    let mut chunk_start = start;
+    let mut decoded = Vec::new();
+    while chunk_start < end {
+        let chunk_len = cmp::min(DECODE_RANGE_CHUNK_SIZE, end - chunk_start);
+
+        decoded.clear();
+        empty_sector_update_decode_from_range(
+            registered_proof,
+            comm_d,
+            comm_r,
+            &mut replica,
+            &mut sector_key,
+            &mut decoded,
+            (chunk_start / NODE_SIZE as u64) as usize,
+            (chunk_len / NODE_SIZE as u64) as usize,
+        )?;
+        output.write_all(&decoded)?;
+
+        chunk_start += chunk_len;
+    }
+
+    Ok(())
+}
+
 fn empty_sector_update_remove_encoded_data_inner<
     Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>,
 >(
@@ -306,14 +620,82 @@ fn generate_partition_proofs_inner<Tree: 'static + MerkleTreeTrait<Hasher = Tree
         replica_cache_path,
     )?;
 
+    let challenge_digest = update_challenge_digest(&comm_r_old, &comm_r_new, &comm_d_new);
     let mut returned_proofs = Vec::with_capacity(partition_proofs.len());
-    for proof in partition_proofs {
-        returned_proofs.push(PartitionProofBytes(bincode::serialize(&proof)?));
+    for (partition_index, proof) in partition_proofs.iter().enumerate() {
+        returned_proofs.push(envelope_partition_proof(
+            registered_proof,
+            partition_index,
+            proof,
+            challenge_digest,
+        )?);
     }
 
     Ok(returned_proofs)
 }
 
+/// Wraps the vanilla proof of a partition in a [`VanillaProofEnvelope`].
+fn envelope_partition_proof<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
+    registered_proof: RegisteredUpdateProof,
+    partition_index: usize,
+    proof: &PartitionProof<Tree>,
+    challenge_digest: [u8; 32],
+) -> Result<PartitionProofBytes> {
+    let envelope = VanillaProofEnvelope {
+        registered_proof,
+        sector_id: None,
+        partition_index: Some(partition_index),
+        challenge_digest,
+        proof: bincode::serialize(proof)?,
+    };
+
+    Ok(PartitionProofBytes(envelope.to_bytes()?))
+}
+
+/// Opens an enveloped partition proof, checking that it was generated for
+/// `registered_proof`, the partition at `partition_index` and the given challenges.
+fn open_partition_proof<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
+    registered_proof: RegisteredUpdateProof,
+    partition_index: usize,
+    proof: &PartitionProofBytes,
+    challenge_digest: &[u8; 32],
+) -> Result<PartitionProof<Tree>> {
+    let envelope = VanillaProofEnvelope::<RegisteredUpdateProof>::from_bytes(&proof.0)?;
+    let proof_bytes = envelope.check(
+        registered_proof,
+        None,
+        Some(partition_index),
+        challenge_digest,
+    )?;
+
+    Ok(bincode::deserialize(proof_bytes)?)
+}
+
+/// Opens enveloped partition proofs, checking that they were generated for
+/// `registered_proof` and the given commitments, in order of their partitions.
+fn open_partition_proofs<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
+    registered_proof: RegisteredUpdateProof,
+    partition_proofs: &[PartitionProofBytes],
+    comm_r_old: &Commitment,
+    comm_r_new: &Commitment,
+    comm_d_new: &Commitment,
+) -> Result<Vec<PartitionProof<Tree>>> {
+    let challenge_digest = update_challenge_digest(comm_r_old, comm_r_new, comm_d_new);
+
+    partition_proofs
+        .iter()
+        .enumerate()
+        .map(|(partition_index, proof)| {
+            open_partition_proof::<Tree>(
+                registered_proof,
+                partition_index,
+                proof,
+                &challenge_digest,
+            )
+        })
+        .collect()
+}
+
 /// Generate all vanilla partition proofs across all partitions.
 ///
 /// # Arguments
@@ -377,11 +759,13 @@ fn verify_partition_proofs_inner<Tree: 'static + MerkleTreeTrait<Hasher = TreeRH
     let config = registered_proof.as_v1_config();
     let sector_config = SectorUpdateConfig::from_porep_config(&config);
 
-    let mut proofs = Vec::with_capacity(partition_proofs.len());
-    for proof in partition_proofs {
-        let proof: PartitionProof<Tree> = bincode::deserialize(&proof.0)?;
-        proofs.push(proof);
-    }
+    let proofs = open_partition_proofs::<Tree>(
+        registered_proof,
+        partition_proofs,
+        &comm_r_old,
+        &comm_r_new,
+        &comm_d_new,
+    )?;
 
     let valid = filecoin_proofs_v1::verify_partition_proofs::<Tree>(
         sector_config,
@@ -427,6 +811,158 @@ pub fn verify_partition_proofs(
     )
 }
 
+fn generate_single_partition_proof_inner<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
+    registered_proof: RegisteredUpdateProof,
+    partition_index: usize,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+    sector_key_path: &Path,
+    sector_key_cache_path: &Path,
+    replica_path: &Path,
+    replica_cache_path: &Path,
+) -> Result<PartitionProofBytes> {
+    let config = registered_proof.as_v1_config();
+    let sector_config = SectorUpdateConfig::from_porep_config(&config);
+
+    let proof = filecoin_proofs_v1::generate_single_partition_proof::<Tree>(
+        sector_config,
+        partition_index,
+        comm_r_old,
+        comm_r_new,
+        comm_d_new,
+        sector_key_path,
+        sector_key_cache_path,
+        replica_path,
+        replica_cache_path,
+    )?;
+
+    envelope_partition_proof(
+        registered_proof,
+        partition_index,
+        &proof,
+        update_challenge_digest(&comm_r_old, &comm_r_new, &comm_d_new),
+    )
+}
+
+/// Generate the vanilla proof of a single partition, so that the partitions of an update can
+/// be proven independently.
+///
+/// # Arguments
+/// * `registered_proof` - Selected sector update proof.
+/// * `partition_index` - Index of the partition to prove.
+/// * `comm_r_old` - Previous replica commitment.
+/// * `comm_r_new` - New replica commitment.
+/// * `comm_d_new` - New data commitment.
+/// * `sector_key_path` - Path to sector key originally used to seal sector.
+/// * `sector_key_cache_path` - Path to write updated `tree_r_last`.
+/// * `replica_path` - File path of new sealed replica.
+/// * `replica_cache_path` - Directory cache path for replica (for `p_aux`).
+///
+/// Returns the partition proof, which takes the place at `partition_index` in the input of
+/// [`verify_partition_proofs`] and [`generate_empty_sector_update_proof_with_vanilla`].
+pub fn generate_single_partition_proof<R, S, T, U>(
+    registered_proof: RegisteredUpdateProof,
+    partition_index: usize,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+    sector_key_path: R,
+    sector_key_cache_path: S,
+    replica_path: T,
+    replica_cache_path: U,
+) -> Result<PartitionProofBytes>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+    U: AsRef<Path>,
+{
+    ensure!(
+        registered_proof.major_version() == 1,
+        "unusupported version"
+    );
+
+    with_shape!(
+        u64::from(registered_proof.sector_size()),
+        generate_single_partition_proof_inner,
+        registered_proof,
+        partition_index,
+        comm_r_old,
+        comm_r_new,
+        comm_d_new,
+        sector_key_path.as_ref(),
+        sector_key_cache_path.as_ref(),
+        replica_path.as_ref(),
+        replica_cache_path.as_ref(),
+    )
+}
+
+fn verify_single_partition_proof_inner<Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>>(
+    registered_proof: RegisteredUpdateProof,
+    partition_index: usize,
+    partition_proof: &PartitionProofBytes,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+) -> Result<bool> {
+    let config = registered_proof.as_v1_config();
+    let sector_config = SectorUpdateConfig::from_porep_config(&config);
+
+    let proof = open_partition_proof::<Tree>(
+        registered_proof,
+        partition_index,
+        partition_proof,
+        &update_challenge_digest(&comm_r_old, &comm_r_new, &comm_d_new),
+    )?;
+
+    filecoin_proofs_v1::verify_single_partition_proof::<Tree>(
+        sector_config,
+        partition_index,
+        proof,
+        comm_r_old,
+        comm_r_new,
+        comm_d_new,
+    )
+}
+
+/// Verify the vanilla proof of a single partition, as returned by
+/// [`generate_single_partition_proof`].
+///
+/// # Arguments
+/// * `registered_proof` - Selected sector update proof.
+/// * `partition_index` - Index of the partition the proof was generated for.
+/// * `partition_proof` - Proof to verify.
+/// * `comm_r_old` - Previous replica commitment.
+/// * `comm_r_new` - New replica commitment.
+/// * `comm_d_new` - New data commitment.
+///
+/// Returns proof verification result.
+pub fn verify_single_partition_proof(
+    registered_proof: RegisteredUpdateProof,
+    partition_index: usize,
+    partition_proof: &PartitionProofBytes,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+) -> Result<bool> {
+    ensure!(
+        registered_proof.major_version() == 1,
+        "unusupported version"
+    );
+
+    with_shape!(
+        u64::from(registered_proof.sector_size()),
+        verify_single_partition_proof_inner,
+        registered_proof,
+        partition_index,
+        partition_proof,
+        comm_r_old,
+        comm_r_new,
+        comm_d_new,
+    )
+}
+
 fn generate_empty_sector_update_proof_inner_with_vanilla<
     Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>,
 >(
@@ -443,11 +979,13 @@ fn generate_empty_sector_update_proof_inner_with_vanilla<
 
     let config = registered_proof.as_v1_config();
 
-    let mut partition_proofs = Vec::with_capacity(vanilla_proofs.len());
-    for proof in vanilla_proofs {
-        let proof: PartitionProof<Tree> = bincode::deserialize(&proof.0)?;
-        partition_proofs.push(proof);
-    }
+    let partition_proofs = open_partition_proofs::<Tree>(
+        registered_proof,
+        &vanilla_proofs,
+        &comm_r_old,
+        &comm_r_new,
+        &comm_d_new,
+    )?;
 
     filecoin_proofs_v1::generate_empty_sector_update_proof_with_vanilla::<Tree>(
         &config,
@@ -491,6 +1029,149 @@ pub fn generate_empty_sector_update_proof_with_vanilla(
     )
 }
 
+fn generate_single_partition_snark_proof_inner<
+    Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>,
+>(
+    registered_proof: RegisteredUpdateProof,
+    partition_index: usize,
+    vanilla_proof: &PartitionProofBytes,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+) -> Result<PartitionSnarkProof> {
+    let config = registered_proof.as_v1_config();
+    let sector_config = SectorUpdateConfig::from_porep_config(&config);
+    let partitions = usize::from(sector_config.update_partitions);
+    ensure!(
+        partition_index < partitions,
+        "partition index {} exceeds the {} partitions of {:?}",
+        partition_index,
+        partitions,
+        registered_proof
+    );
+
+    let vanilla_proof = open_partition_proof::<Tree>(
+        registered_proof,
+        partition_index,
+        vanilla_proof,
+        &update_challenge_digest(&comm_r_old, &comm_r_new, &comm_d_new),
+    )?;
+
+    let pub_inputs = storage_proofs_update::PublicInputs {
+        k: partition_index,
+        comm_r_old: <TreeRHasher as Hasher>::Domain::try_from_bytes(&comm_r_old)?,
+        comm_d_new: <DefaultPieceHasher as Hasher>::Domain::try_from_bytes(&comm_d_new)?,
+        comm_r_new: <TreeRHasher as Hasher>::Domain::try_from_bytes(&comm_r_new)?,
+        h: sector_config.h,
+    };
+    let setup_params = compound_proof::SetupParams {
+        vanilla_params: storage_proofs_update::SetupParams {
+            sector_bytes: u64::from(sector_config.sector_size),
+        },
+        partitions: Some(partitions),
+        priority: false,
+    };
+    let pub_params = EmptySectorUpdateCompound::<Tree>::setup(&setup_params)?;
+
+    // `filecoin_proofs_v1` only proves all partitions at once, numbering the circuits by their
+    // position in the given vanilla proofs. Builds the same circuit as the partition at
+    // `partition_index` of the full proof instead.
+    let circuit = EmptySectorUpdateCompound::<Tree>::circuit(
+        &pub_inputs,
+        Default::default(),
+        &vanilla_proof,
+        &pub_params.vanilla_params,
+        Some(partition_index),
+    )?;
+    let groth_params = filecoin_proofs_v1::caches::get_empty_sector_update_params::<Tree>(&config)?;
+    let proof = groth16::create_random_proof(circuit, &*groth_params, &mut OsRng)?;
+
+    let mut proof_bytes = Vec::with_capacity(SINGLE_PARTITION_PROOF_LEN);
+    proof.write(&mut proof_bytes)?;
+
+    Ok(PartitionSnarkProof(proof_bytes))
+}
+
+/// Generate the zk-SNARK proof of a single partition of an update, to be merged with those
+/// of the other partitions by [`merge_empty_sector_update_partition_proofs`].
+///
+/// # Arguments
+/// * `registered_proof` - Selected sector update proof.
+/// * `partition_index` - Index of the partition to prove.
+/// * `vanilla_proof` - Vanilla proof of the partition, as returned by
+///    [`generate_single_partition_proof`] or at `partition_index` by
+///    [`generate_partition_proofs`].
+/// * `comm_r_old` - Previous replica commitment.
+/// * `comm_r_new` - New replica commitment.
+/// * `comm_d_new` - New data commitment.
+///
+/// Returns [`PartitionSnarkProof`] for the partition.
+pub fn generate_single_partition_snark_proof(
+    registered_proof: RegisteredUpdateProof,
+    partition_index: usize,
+    vanilla_proof: &PartitionProofBytes,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+) -> Result<PartitionSnarkProof> {
+    ensure!(
+        registered_proof.major_version() == 1,
+        "unusupported version"
+    );
+
+    with_shape!(
+        u64::from(registered_proof.sector_size()),
+        generate_single_partition_snark_proof_inner,
+        registered_proof,
+        partition_index,
+        vanilla_proof,
+        comm_r_old,
+        comm_r_new,
+        comm_d_new,
+    )
+}
+
+/// Merges the zk-SNARK proofs of all partitions of an update into a single proof.
+///
+/// # Arguments
+/// * `registered_proof` - Selected sector update proof.
+/// * `proofs` - The partition proofs returned by [`generate_single_partition_snark_proof`],
+///    in order of their partitions.
+///
+/// Returns the merged [`EmptySectorUpdateProof`], to be verified with
+/// [`verify_empty_sector_update_proof`].
+pub fn merge_empty_sector_update_partition_proofs(
+    registered_proof: RegisteredUpdateProof,
+    proofs: Vec<PartitionSnarkProof>,
+) -> Result<EmptySectorUpdateProof> {
+    ensure!(
+        registered_proof.major_version() == 1,
+        "unusupported version"
+    );
+
+    let config = registered_proof.as_v1_config();
+    let partitions = usize::from(SectorUpdateConfig::from_porep_config(&config).update_partitions);
+    ensure!(
+        proofs.len() == partitions,
+        "expected {} partition proofs, got {}",
+        partitions,
+        proofs.len()
+    );
+    ensure!(
+        proofs
+            .iter()
+            .all(|proof| proof.0.len() == SINGLE_PARTITION_PROOF_LEN),
+        "partition proofs must be {} bytes",
+        SINGLE_PARTITION_PROOF_LEN
+    );
+
+    // The merged proof consists of the partition proofs in order, as serialized by a
+    // multi-partition proof.
+    Ok(EmptySectorUpdateProof(
+        proofs.into_iter().flat_map(|proof| proof.0).collect(),
+    ))
+}
+
 fn generate_empty_sector_update_proof_inner<
     Tree: 'static + MerkleTreeTrait<Hasher = TreeRHasher>,
 >(
@@ -621,3 +1302,664 @@ pub fn verify_empty_sector_update_proof(
         comm_d_new,
     )
 }
+
+/// File name of the state persisted by an [`UpdatePipeline`] within the new cache directory.
+pub const UPDATE_PIPELINE_STATE_FILE: &str = "update-pipeline-state";
+
+/// The full update of a sector, from encoding the staged data to the verified update proof.
+///
+/// The outcome of every step is persisted to [`UPDATE_PIPELINE_STATE_FILE`] within the new
+/// cache directory, so that a pipeline reopened with [`UpdatePipeline::open`] after a crash
+/// resumes after the last completed step.
+///  * `registered_proof` - Selected sector update proof.
+///  * `comm_r_old` - Replica commitment of the sector key.
+///  * `sector_key_path` - Path to sector key originally used to seal sector.
+///  * `sector_key_cache_path` - Cache directory of the sector key.
+///  * `new_replica_path` - Output path of new updated replica.
+///  * `new_cache_path` - Output path of new cache, which holds the pipeline state.
+///  * `staged_data_path` - Path to staged data to encode into existing replica.
+///  * `piece_infos` - The piece info (commitment and byte length) for each piece in the sector.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UpdatePipeline {
+    pub registered_proof: RegisteredUpdateProof,
+    pub comm_r_old: Commitment,
+    pub sector_key_path: PathBuf,
+    pub sector_key_cache_path: PathBuf,
+    pub new_replica_path: PathBuf,
+    pub new_cache_path: PathBuf,
+    pub staged_data_path: PathBuf,
+    pub piece_infos: Vec<PieceInfo>,
+    encoded: Option<UpdatePipelineEncoded>,
+    partition_proofs: Option<Vec<PartitionProofBytes>>,
+    proof: Option<Vec<u8>>,
+}
+
+/// The new commitments of an update, as persisted by an [`UpdatePipeline`].
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct UpdatePipelineEncoded {
+    comm_r_new: Commitment,
+    comm_r_last_new: Commitment,
+    comm_d_new: Commitment,
+}
+
+impl From<&EmptySectorUpdateEncoded> for UpdatePipelineEncoded {
+    fn from(encoded: &EmptySectorUpdateEncoded) -> Self {
+        UpdatePipelineEncoded {
+            comm_r_new: encoded.comm_r_new,
+            comm_r_last_new: encoded.comm_r_last_new,
+            comm_d_new: encoded.comm_d_new,
+        }
+    }
+}
+
+impl From<UpdatePipelineEncoded> for EmptySectorUpdateEncoded {
+    fn from(encoded: UpdatePipelineEncoded) -> Self {
+        EmptySectorUpdateEncoded {
+            comm_r_new: encoded.comm_r_new,
+            comm_r_last_new: encoded.comm_r_last_new,
+            comm_d_new: encoded.comm_d_new,
+        }
+    }
+}
+
+impl UpdatePipeline {
+    /// Opens the pipeline of an update, resuming from the state within `new_cache_path` if
+    /// there is any. The persisted state must be that of the same update.
+    ///
+    /// # Arguments
+    /// * `registered_proof` - Selected sector update proof.
+    /// * `comm_r_old` - Replica commitment of the sector key.
+    /// * `sector_key_path` - Path to sector key originally used to seal sector.
+    /// * `sector_key_cache_path` - Cache directory of the sector key.
+    /// * `new_replica_path` - Output path of new updated replica.
+    /// * `new_cache_path` - Output path of new cache.
+    /// * `staged_data_path` - Path to staged data to encode into existing replica.
+    /// * `piece_infos` - The piece info (commitment and byte length) for each piece in the sector.
+    pub fn open<R, S, T, U, V>(
+        registered_proof: RegisteredUpdateProof,
+        comm_r_old: Commitment,
+        sector_key_path: R,
+        sector_key_cache_path: S,
+        new_replica_path: T,
+        new_cache_path: U,
+        staged_data_path: V,
+        piece_infos: &[PieceInfo],
+    ) -> Result<Self>
+    where
+        R: AsRef<Path>,
+        S: AsRef<Path>,
+        T: AsRef<Path>,
+        U: AsRef<Path>,
+        V: AsRef<Path>,
+    {
+        ensure!(
+            registered_proof.major_version() == 1,
+            "unusupported version"
+        );
+
+        let pipeline = UpdatePipeline {
+            registered_proof,
+            comm_r_old,
+            sector_key_path: sector_key_path.as_ref().to_path_buf(),
+            sector_key_cache_path: sector_key_cache_path.as_ref().to_path_buf(),
+            new_replica_path: new_replica_path.as_ref().to_path_buf(),
+            new_cache_path: new_cache_path.as_ref().to_path_buf(),
+            staged_data_path: staged_data_path.as_ref().to_path_buf(),
+            piece_infos: piece_infos.to_vec(),
+            encoded: None,
+            partition_proofs: None,
+            proof: None,
+        };
+
+        let state_path = pipeline.state_path();
+        if !state_path.exists() {
+            return Ok(pipeline);
+        }
+
+        let file = File::open(&state_path)
+            .with_context(|| format!("could not open update state {:?}", state_path))?;
+        let persisted: UpdatePipeline = bincode::deserialize_from(BufReader::new(file))
+            .with_context(|| format!("could not parse update state {:?}", state_path))?;
+        ensure!(
+            persisted.registered_proof == pipeline.registered_proof
+                && persisted.comm_r_old == pipeline.comm_r_old
+                && persisted.sector_key_path == pipeline.sector_key_path
+                && persisted.sector_key_cache_path == pipeline.sector_key_cache_path
+                && persisted.new_replica_path == pipeline.new_replica_path
+                && persisted.staged_data_path == pipeline.staged_data_path
+                && persisted.piece_infos == pipeline.piece_infos,
+            "update state {:?} belongs to a different update",
+            state_path
+        );
+
+        Ok(UpdatePipeline {
+            new_cache_path: pipeline.new_cache_path,
+            ..persisted
+        })
+    }
+
+    /// The new commitments, once the staged data is encoded.
+    pub fn encoded(&self) -> Option<EmptySectorUpdateEncoded> {
+        self.encoded.map(Into::into)
+    }
+
+    /// The bytes of the update proof, once it is generated.
+    pub fn proof(&self) -> Option<&[u8]> {
+        self.proof.as_deref()
+    }
+
+    /// Runs the remaining steps of the update: encoding the staged data, generating the
+    /// vanilla partition proofs and the update proof, and finally verifying the update proof.
+    /// Each completed step is persisted before the next one starts.
+    ///
+    /// A proof which fails the final verification is discarded, so that it is generated anew
+    /// when the pipeline is run again.
+    ///
+    /// Returns the new commitments in [`EmptySectorUpdateEncoded`] and the verified
+    /// [`EmptySectorUpdateProof`].
+    pub fn run(&mut self) -> Result<(EmptySectorUpdateEncoded, EmptySectorUpdateProof)> {
+        let encoded = match self.encoded {
+            Some(encoded) => encoded,
+            None => {
+                let encoded = empty_sector_update_encode_into(
+                    self.registered_proof,
+                    &self.new_replica_path,
+                    &self.new_cache_path,
+                    &self.sector_key_path,
+                    &self.sector_key_cache_path,
+                    &self.staged_data_path,
+                    &self.piece_infos,
+                )?;
+                let encoded = UpdatePipelineEncoded::from(&encoded);
+                self.encoded = Some(encoded);
+                self.save()?;
+
+                encoded
+            }
+        };
+
+        if self.proof.is_none() {
+            let partition_proofs = match &self.partition_proofs {
+                Some(partition_proofs) => partition_proofs.clone(),
+                None => {
+                    let partition_proofs = generate_partition_proofs(
+                        self.registered_proof,
+                        self.comm_r_old,
+                        encoded.comm_r_new,
+                        encoded.comm_d_new,
+                        &self.sector_key_path,
+                        &self.sector_key_cache_path,
+                        &self.new_replica_path,
+                        &self.new_cache_path,
+                    )?;
+                    self.partition_proofs = Some(partition_proofs.clone());
+                    self.save()?;
+
+                    partition_proofs
+                }
+            };
+
+            let proof = generate_empty_sector_update_proof_with_vanilla(
+                self.registered_proof,
+                partition_proofs,
+                self.comm_r_old,
+                encoded.comm_r_new,
+                encoded.comm_d_new,
+            )?;
+            // The vanilla proofs are no longer needed once the update proof exists.
+            self.partition_proofs = None;
+            self.proof = Some(proof.0);
+            self.save()?;
+        }
+
+        let proof = self.proof.clone().expect("update proof missing");
+        let valid = verify_empty_sector_update_proof(
+            self.registered_proof,
+            &proof,
+            self.comm_r_old,
+            encoded.comm_r_new,
+            encoded.comm_d_new,
+        )?;
+        if !valid {
+            self.proof = None;
+            self.save()?;
+        }
+        ensure!(valid, "generated update proof failed to verify");
+
+        Ok((encoded.into(), EmptySectorUpdateProof(proof)))
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.new_cache_path.join(UPDATE_PIPELINE_STATE_FILE)
+    }
+
+    /// Persists the state, replacing the previous state only once the new one is written.
+    fn save(&self) -> Result<()> {
+        let state_path = self.state_path();
+        let tmp_path = state_path.with_extension("tmp");
+
+        let file = File::create(&tmp_path)
+            .with_context(|| format!("could not create update state {:?}", tmp_path))?;
+        let mut writer = BufWriter::new(file);
+        bincode::serialize_into(&mut writer, self)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&tmp_path, &state_path)
+            .with_context(|| format!("could not write update state {:?}", state_path))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes a piece into a faux 2KiB sector key below `dir`, returning the commitment of
+    /// the sector key, the new commitments and the piece data.
+    fn encode_test_sector(dir: &Path) -> (Commitment, EmptySectorUpdateEncoded, Vec<u8>) {
+        let data: Vec<u8> = (0..2032).map(|i| (i * 7 % 251) as u8).collect();
+        let (comm_r_old, encoded, _) = encode_test_pieces(dir, &[data.clone()]);
+
+        (comm_r_old, encoded, data)
+    }
+
+    /// Like `encode_test_sector`, but encodes the given pieces laid out as by `add_piece`,
+    /// returning their piece infos instead of the data.
+    fn encode_test_pieces(
+        dir: &Path,
+        pieces: &[Vec<u8>],
+    ) -> (Commitment, EmptySectorUpdateEncoded, Vec<PieceInfo>) {
+        use crate::padding::PadWriter;
+        use crate::seal::{fauxrep, generate_piece_commitment};
+        use crate::RegisteredSealProof;
+
+        let sector_key_cache_path = dir.join("sector-key-cache");
+        let new_cache_path = dir.join("cache");
+        fs::create_dir_all(&sector_key_cache_path).expect("failed to create cache dir");
+        fs::create_dir_all(&new_cache_path).expect("failed to create cache dir");
+        let sector_key_path = dir.join("sector-key");
+        let replica_path = dir.join("replica");
+        let staged_data_path = dir.join("staged");
+
+        let registered_proof = RegisteredUpdateProof::StackedDrg2KiBV1;
+        let registered_seal_proof = RegisteredSealProof::StackedDrg2KiBV1_1;
+        let comm_r_old = fauxrep(
+            registered_seal_proof,
+            &sector_key_cache_path,
+            &sector_key_path,
+        )
+        .expect("failed to create sector key");
+
+        let mut data = Vec::new();
+        let mut piece_sizes = Vec::new();
+        let mut piece_infos = Vec::new();
+        for piece in pieces {
+            let size = UnpaddedBytesAmount(piece.len() as u64);
+            let start = u64::from(get_piece_start_byte(&piece_sizes, size));
+            data.resize(start as usize, 0);
+            data.extend_from_slice(piece);
+            piece_sizes.push(size);
+            piece_infos.push(
+                generate_piece_commitment(registered_seal_proof, &piece[..], size)
+                    .expect("failed to commit piece"),
+            );
+        }
+        data.resize(2032, 0);
+        let mut staged = PadWriter::new(File::create(&staged_data_path).expect("create failed"));
+        staged
+            .write_all(&data)
+            .expect("failed to write staged data");
+        staged.finish().expect("failed to pad staged data");
+
+        let encoded = empty_sector_update_encode_into(
+            registered_proof,
+            &replica_path,
+            &new_cache_path,
+            &sector_key_path,
+            &sector_key_cache_path,
+            &staged_data_path,
+            &piece_infos,
+        )
+        .expect("failed to encode");
+
+        (comm_r_old, encoded, piece_infos)
+    }
+
+    #[test]
+    fn test_decode_from_parallel() {
+        let dir = std::env::temp_dir().join(format!("decode-parallel-{}", std::process::id()));
+        let registered_proof = RegisteredUpdateProof::StackedDrg2KiBV1;
+        let (comm_r_old, encoded, data) = encode_test_sector(&dir);
+        let sector_key_path = dir.join("sector-key");
+        let sector_key_cache_path = dir.join("sector-key-cache");
+        let replica_path = dir.join("replica");
+
+        let serial_path = dir.join("serial");
+        empty_sector_update_decode_from(
+            registered_proof,
+            &serial_path,
+            &replica_path,
+            &sector_key_path,
+            &sector_key_cache_path,
+            encoded.comm_d_new,
+        )
+        .expect("failed to decode");
+        let serial = fs::read(&serial_path).expect("failed to read output");
+
+        for num_threads in &[1, 3] {
+            let parallel_path = dir.join(format!("parallel-{}", num_threads));
+            empty_sector_update_decode_from_parallel(
+                registered_proof,
+                &parallel_path,
+                &replica_path,
+                &sector_key_path,
+                encoded.comm_d_new,
+                comm_r_old,
+                *num_threads,
+            )
+            .expect("failed to decode in parallel");
+            assert_eq!(
+                fs::read(&parallel_path).expect("failed to read output"),
+                serial
+            );
+        }
+
+        let mut range = Vec::new();
+        empty_sector_update_decode_unpadded_range(
+            registered_proof,
+            encoded.comm_d_new,
+            comm_r_old,
+            &replica_path,
+            &sector_key_path,
+            &mut range,
+            UnpaddedByteIndex(100),
+            UnpaddedBytesAmount(300),
+        )
+        .expect("failed to decode range");
+        assert_eq!(range, &data[100..400]);
+
+        fs::remove_dir_all(&dir).expect("failed to remove test dir");
+    }
+
+    #[test]
+    fn test_verify_decoded_aligned_pieces() {
+        let dir = std::env::temp_dir().join(format!("verify-decoded-{}", std::process::id()));
+        let registered_proof = RegisteredUpdateProof::StackedDrg2KiBV1;
+        // The second piece starts at byte 1016, not right after the first one.
+        let small: Vec<u8> = (0..127).map(|i| (i * 3 % 251) as u8).collect();
+        let large: Vec<u8> = (0..1016).map(|i| (i * 5 % 251) as u8).collect();
+        let (_, encoded, piece_infos) = encode_test_pieces(&dir, &[small, large]);
+
+        let out_data_path = dir.join("decoded");
+        empty_sector_update_decode_from(
+            registered_proof,
+            &out_data_path,
+            dir.join("replica"),
+            dir.join("sector-key"),
+            dir.join("sector-key-cache"),
+            encoded.comm_d_new,
+        )
+        .expect("failed to decode");
+        verify_empty_sector_update_decoded(
+            registered_proof,
+            &out_data_path,
+            encoded.comm_d_new,
+            &piece_infos,
+        )
+        .expect("decoded pieces rejected");
+
+        assert!(verify_empty_sector_update_decoded(
+            registered_proof,
+            &out_data_path,
+            encoded.comm_d_new,
+            &piece_infos[..1],
+        )
+        .is_err());
+
+        fs::remove_dir_all(&dir).expect("failed to remove test dir");
+    }
+
+    #[test]
+    fn test_decode_unpadded_range() {
+        let dir = std::env::temp_dir().join(format!("decode-range-{}", std::process::id()));
+        let registered_proof = RegisteredUpdateProof::StackedDrg2KiBV1;
+        let (comm_r_old, encoded, data) = encode_test_sector(&dir);
+        let sector_key_path = dir.join("sector-key");
+        let replica_path = dir.join("replica");
+
+        // Ranges starting and ending within Fr32 blocks, on block boundaries and at the ends
+        // of the sector, decoded one or more blocks at a time.
+        let ranges = [
+            (0, 2032),
+            (0, 1),
+            (126, 2),
+            (127, 127),
+            (100, 300),
+            (1000, 1032),
+            (2031, 1),
+            (500, 0),
+        ];
+        for chunk_size in &[128, 384, DECODE_RANGE_CHUNK_SIZE] {
+            for (offset, num_bytes) in &ranges {
+                let mut range = Vec::new();
+                let written = decode_unpadded_range(
+                    registered_proof,
+                    encoded.comm_d_new,
+                    comm_r_old,
+                    &replica_path,
+                    &sector_key_path,
+                    &mut range,
+                    UnpaddedByteIndex(*offset),
+                    UnpaddedBytesAmount(*num_bytes),
+                    *chunk_size,
+                )
+                .expect("failed to decode range");
+                assert_eq!(written, UnpaddedBytesAmount(*num_bytes));
+                assert_eq!(
+                    range,
+                    &data[*offset as usize..(offset + num_bytes) as usize],
+                    "range {}+{} decoded {} bytes at a time",
+                    offset,
+                    num_bytes,
+                    chunk_size
+                );
+            }
+        }
+
+        assert!(empty_sector_update_decode_unpadded_range(
+            registered_proof,
+            encoded.comm_d_new,
+            comm_r_old,
+            &replica_path,
+            &sector_key_path,
+            &mut Vec::new(),
+            UnpaddedByteIndex(2000),
+            UnpaddedBytesAmount(33),
+        )
+        .is_err());
+
+        fs::remove_dir_all(&dir).expect("failed to remove test dir");
+    }
+
+    #[test]
+    fn test_update_pipeline_resume() {
+        let dir = std::env::temp_dir().join(format!("update-pipeline-{}", std::process::id()));
+        let new_cache_path = dir.join("cache");
+        fs::create_dir_all(&new_cache_path).expect("failed to create cache dir");
+        let registered_proof = RegisteredUpdateProof::StackedDrg2KiBV1;
+        let open = |comm_r_old: Commitment| {
+            UpdatePipeline::open(
+                registered_proof,
+                comm_r_old,
+                dir.join("sector-key"),
+                dir.join("sector-key-cache"),
+                dir.join("replica"),
+                &new_cache_path,
+                dir.join("staged"),
+                &[],
+            )
+        };
+
+        let mut pipeline = open([1; 32]).expect("failed to open pipeline");
+        assert!(pipeline.encoded().is_none());
+
+        pipeline.encoded = Some(UpdatePipelineEncoded {
+            comm_r_new: [2; 32],
+            comm_r_last_new: [3; 32],
+            comm_d_new: [4; 32],
+        });
+        pipeline.save().expect("failed to save state");
+
+        let resumed = open([1; 32]).expect("failed to resume pipeline");
+        assert_eq!(
+            resumed.encoded().map(|encoded| encoded.comm_r_new),
+            Some([2; 32])
+        );
+        assert!(resumed.proof().is_none());
+
+        let err = open([5; 32]).expect_err("state of a different update accepted");
+        assert!(err.to_string().contains("different update"));
+
+        fs::remove_dir_all(&dir).expect("failed to remove test dir");
+    }
+
+    #[test]
+    #[cfg(feature = "big-tests")]
+    fn test_update_pipeline_run_resumes_each_stage() {
+        let dir = std::env::temp_dir().join(format!("update-pipeline-run-{}", std::process::id()));
+        let registered_proof = RegisteredUpdateProof::StackedDrg2KiBV1;
+        let data: Vec<u8> = (0..2032).map(|i| (i * 11 % 251) as u8).collect();
+        let (comm_r_old, encoded, piece_infos) = encode_test_pieces(&dir, &[data]);
+        let open = || {
+            UpdatePipeline::open(
+                registered_proof,
+                comm_r_old,
+                dir.join("sector-key"),
+                dir.join("sector-key-cache"),
+                dir.join("replica"),
+                dir.join("cache"),
+                dir.join("staged"),
+                &piece_infos,
+            )
+            .expect("failed to open pipeline")
+        };
+
+        // Resume after encoding: the staged data is gone, so it cannot be encoded again.
+        let mut pipeline = open();
+        pipeline.encoded = Some((&encoded).into());
+        pipeline.save().expect("failed to save state");
+        fs::remove_file(dir.join("staged")).expect("failed to remove staged data");
+
+        let (resumed, proof) = open().run().expect("failed to resume after encoding");
+        assert_eq!(resumed.comm_r_new, encoded.comm_r_new);
+        assert_eq!(resumed.comm_r_last_new, encoded.comm_r_last_new);
+        assert_eq!(resumed.comm_d_new, encoded.comm_d_new);
+        assert!(!proof.0.is_empty());
+
+        // Resume after the partition proofs: the sector key is gone, so they cannot be
+        // generated again.
+        let mut pipeline = open();
+        pipeline.partition_proofs = Some(
+            generate_partition_proofs(
+                registered_proof,
+                comm_r_old,
+                encoded.comm_r_new,
+                encoded.comm_d_new,
+                dir.join("sector-key"),
+                dir.join("sector-key-cache"),
+                dir.join("replica"),
+                dir.join("cache"),
+            )
+            .expect("failed to generate partition proofs"),
+        );
+        pipeline.proof = None;
+        pipeline.save().expect("failed to save state");
+        fs::remove_file(dir.join("sector-key")).expect("failed to remove sector key");
+
+        let mut pipeline = open();
+        let (_, proof) = pipeline
+            .run()
+            .expect("failed to resume after partition proofs");
+        assert!(pipeline.partition_proofs.is_none());
+
+        // Resume after the proof: proving is randomized, so the same bytes mean it was not
+        // generated again.
+        let pipeline = open();
+        assert_eq!(pipeline.proof(), Some(&proof.0[..]));
+        let (_, resumed_proof) = open().run().expect("failed to resume after proof");
+        assert_eq!(resumed_proof.0, proof.0);
+
+        fs::remove_dir_all(&dir).expect("failed to remove test dir");
+    }
+
+    #[test]
+    fn test_single_partition_proofs() {
+        let dir = std::env::temp_dir().join(format!("update-partition-{}", std::process::id()));
+        let registered_proof = RegisteredUpdateProof::StackedDrg2KiBV1;
+        let (comm_r_old, encoded, _) = encode_test_sector(&dir);
+
+        let prove = |partition_index| {
+            generate_single_partition_proof(
+                registered_proof,
+                partition_index,
+                comm_r_old,
+                encoded.comm_r_new,
+                encoded.comm_d_new,
+                dir.join("sector-key"),
+                dir.join("sector-key-cache"),
+                dir.join("replica"),
+                dir.join("cache"),
+            )
+            .expect("failed to generate partition proof")
+        };
+        let verify = |partition_index, proof: &PartitionProofBytes| {
+            verify_single_partition_proof(
+                registered_proof,
+                partition_index,
+                proof,
+                comm_r_old,
+                encoded.comm_r_new,
+                encoded.comm_d_new,
+            )
+        };
+
+        let proof = prove(0);
+        assert!(verify(0, &proof).expect("failed to verify partition proof"));
+        assert!(verify(1, &proof).is_err());
+
+        let partition_proofs = vec![proof];
+        assert!(verify_partition_proofs(
+            registered_proof,
+            &partition_proofs,
+            comm_r_old,
+            encoded.comm_r_new,
+            encoded.comm_d_new,
+        )
+        .expect("failed to verify partition proofs"));
+
+        fs::remove_dir_all(&dir).expect("failed to remove test dir");
+    }
+
+    #[test]
+    fn test_merge_empty_sector_update_partition_proofs() {
+        let registered_proof = RegisteredUpdateProof::StackedDrg2KiBV1;
+        let proof = PartitionSnarkProof(vec![7; SINGLE_PARTITION_PROOF_LEN]);
+
+        let merged =
+            merge_empty_sector_update_partition_proofs(registered_proof, vec![proof.clone()])
+                .expect("failed to merge proofs");
+        assert_eq!(merged.0, proof.0);
+
+        assert!(merge_empty_sector_update_partition_proofs(
+            registered_proof,
+            vec![proof.clone(), proof]
+        )
+        .is_err());
+        assert!(merge_empty_sector_update_partition_proofs(
+            registered_proof,
+            vec![PartitionSnarkProof(vec![7; 10])]
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,101 @@
+#[test]
+#[cfg(feature = "big-tests")]
+fn update_partition_snark_proofs() {
+    use filecoin_proofs_api::padding::PadWriter;
+    use filecoin_proofs_api::seal::{fauxrep, generate_piece_commitment};
+    use filecoin_proofs_api::update::{
+        empty_sector_update_encode_into, generate_partition_proofs,
+        generate_single_partition_snark_proof, merge_empty_sector_update_partition_proofs,
+        verify_empty_sector_update_proof,
+    };
+    use filecoin_proofs_api::{RegisteredSealProof, RegisteredUpdateProof, UnpaddedBytesAmount};
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    let dir = std::env::temp_dir().join(format!("update-partitions-{}", std::process::id()));
+    let sector_key_cache_path = dir.join("sector-key-cache");
+    let replica_cache_path = dir.join("cache");
+    fs::create_dir_all(&sector_key_cache_path).unwrap();
+    fs::create_dir_all(&replica_cache_path).unwrap();
+    let sector_key_path = dir.join("sector-key");
+    let replica_path = dir.join("replica");
+    let staged_data_path = dir.join("staged");
+
+    let registered_proof = RegisteredUpdateProof::StackedDrg2KiBV1;
+    let registered_seal_proof = RegisteredSealProof::StackedDrg2KiBV1_1;
+    let comm_r_old = fauxrep(
+        registered_seal_proof,
+        &sector_key_cache_path,
+        &sector_key_path,
+    )
+    .unwrap();
+
+    let data: Vec<u8> = (0..2032).map(|i| (i * 13 % 251) as u8).collect();
+    let piece_info =
+        generate_piece_commitment(registered_seal_proof, &data[..], UnpaddedBytesAmount(2032))
+            .unwrap();
+    let mut staged = PadWriter::new(File::create(&staged_data_path).unwrap());
+    staged.write_all(&data).unwrap();
+    staged.finish().unwrap();
+
+    let encoded = empty_sector_update_encode_into(
+        registered_proof,
+        &replica_path,
+        &replica_cache_path,
+        &sector_key_path,
+        &sector_key_cache_path,
+        &staged_data_path,
+        &[piece_info],
+    )
+    .unwrap();
+
+    let vanilla_proofs = generate_partition_proofs(
+        registered_proof,
+        comm_r_old,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+        &sector_key_path,
+        &sector_key_cache_path,
+        &replica_path,
+        &replica_cache_path,
+    )
+    .unwrap();
+
+    // Every partition is proven on its own, as on separate hosts.
+    let partition_proofs = vanilla_proofs
+        .iter()
+        .enumerate()
+        .map(|(partition_index, vanilla_proof)| {
+            generate_single_partition_snark_proof(
+                registered_proof,
+                partition_index,
+                vanilla_proof,
+                comm_r_old,
+                encoded.comm_r_new,
+                encoded.comm_d_new,
+            )
+            .unwrap()
+        })
+        .collect();
+    let proof =
+        merge_empty_sector_update_partition_proofs(registered_proof, partition_proofs).unwrap();
+
+    assert!(verify_empty_sector_update_proof(
+        registered_proof,
+        &proof.0,
+        comm_r_old,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+    )
+    .unwrap());
+    assert!(!verify_empty_sector_update_proof(
+        registered_proof,
+        &proof.0,
+        comm_r_old,
+        encoded.comm_r_new,
+        comm_r_old,
+    )
+    .unwrap_or(false));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
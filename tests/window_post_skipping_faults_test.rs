@@ -0,0 +1,58 @@
+#[test]
+#[cfg(feature = "big-tests")]
+fn window_post_skipping_faults() {
+    use filecoin_proofs_api::post::{generate_window_post_skipping_faults, verify_window_post};
+    use filecoin_proofs_api::seal::fauxrep;
+    use filecoin_proofs_api::{
+        PrivateReplicaInfo, PublicReplicaInfo, RegisteredPoStProof, RegisteredSealProof, SectorId,
+    };
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("skipping-faults-{}", std::process::id()));
+    let registered_proof = RegisteredSealProof::StackedDrg2KiBV1_1;
+    let registered_post_proof = RegisteredPoStProof::StackedDrgWindow2KiBV1_2;
+    let sector_count = registered_post_proof.sector_count();
+    let randomness = [7; 32];
+    let prover_id = [8; 32];
+
+    // Two full partitions and a partial one, with a fault in the second partition.
+    let num_sectors = 2 * sector_count + 1;
+    let faulty = SectorId::from(sector_count as u64 + 1);
+    let mut replicas = BTreeMap::new();
+    for i in 0..num_sectors as u64 {
+        let cache_dir = dir.join(i.to_string()).join("cache");
+        let replica_path = dir.join(i.to_string()).join("sealed");
+        fs::create_dir_all(&cache_dir).unwrap();
+        let comm_r = fauxrep(registered_proof, &cache_dir, &replica_path).unwrap();
+        replicas.insert(
+            SectorId::from(i),
+            PrivateReplicaInfo::new(registered_post_proof, comm_r, cache_dir, replica_path),
+        );
+    }
+    fs::remove_file(replicas[&faulty].replica_path()).unwrap();
+
+    let (proofs, skipped) =
+        generate_window_post_skipping_faults(&randomness, &replicas, prover_id).unwrap();
+    assert_eq!(skipped, vec![faulty]);
+    assert_eq!(proofs.len(), 1);
+    assert_eq!(proofs[0].0, registered_post_proof);
+
+    let public_replicas: BTreeMap<SectorId, PublicReplicaInfo> = replicas
+        .iter()
+        .filter(|(sector_id, _)| **sector_id != faulty)
+        .map(|(sector_id, replica)| {
+            (
+                *sector_id,
+                PublicReplicaInfo::new(replica.registered_proof(), replica.comm_r()),
+            )
+        })
+        .collect();
+    let proofs: Vec<(RegisteredPoStProof, &[u8])> = proofs
+        .iter()
+        .map(|(registered_proof, proof)| (*registered_proof, &proof[..]))
+        .collect();
+    assert!(verify_window_post(&randomness, &proofs, &public_replicas, prover_id).unwrap());
+
+    fs::remove_dir_all(&dir).unwrap();
+}